@@ -1,12 +1,38 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20241001_000002_create_availability;
+mod m20241015_000003_create_watch_rule;
+mod m20241020_000004_add_auto_book;
+mod m20241025_000005_create_referral_reminder;
+mod m20241101_000006_create_appointment_reminder;
+mod m20241105_000007_add_poll_state;
+mod m20241110_000008_add_poll_failures;
+mod m20241115_000009_add_profiles;
+mod m20241120_000010_add_verified;
+mod m20241125_000011_create_callback_session;
+mod m20241130_000012_add_profile_failures;
+mod m20241205_000013_index_next_poll_at;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20241001_000002_create_availability::Migration),
+            Box::new(m20241015_000003_create_watch_rule::Migration),
+            Box::new(m20241020_000004_add_auto_book::Migration),
+            Box::new(m20241025_000005_create_referral_reminder::Migration),
+            Box::new(m20241101_000006_create_appointment_reminder::Migration),
+            Box::new(m20241105_000007_add_poll_state::Migration),
+            Box::new(m20241110_000008_add_poll_failures::Migration),
+            Box::new(m20241115_000009_add_profiles::Migration),
+            Box::new(m20241120_000010_add_verified::Migration),
+            Box::new(m20241125_000011_create_callback_session::Migration),
+            Box::new(m20241130_000012_add_profile_failures::Migration),
+            Box::new(m20241205_000013_index_next_poll_at::Migration),
+        ]
     }
 }
@@ -0,0 +1,66 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Availability {
+    Table,
+    Id,
+    InfoId,
+    ReferralId,
+    Snapshot,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Info {
+    Table,
+    Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                sea_query::Table::create()
+                    .table(Availability::Table)
+                    .if_not_exists()
+                    .col(pk_auto(Availability::Id))
+                    .col(integer(Availability::InfoId))
+                    .col(big_integer(Availability::ReferralId))
+                    .col(text(Availability::Snapshot))
+                    .col(date_time(Availability::UpdatedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Availability::Table, Availability::InfoId)
+                            .to(Info::Table, Info::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .to_owned()
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_availability_info_referral")
+                    .table(Availability::Table)
+                    .col(Availability::InfoId)
+                    .col(Availability::ReferralId)
+                    .unique()
+                    .to_owned()
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .drop_table(Table::drop().table(Availability::Table).to_owned())
+            .await
+    }
+}
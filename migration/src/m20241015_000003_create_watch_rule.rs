@@ -0,0 +1,62 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum WatchRule {
+    Table,
+    Id,
+    InfoId,
+    ReferralId,
+    DoctorId,
+    DoctorName,
+    LpuId,
+    DateFrom,
+    DateTo,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Info {
+    Table,
+    Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                sea_query::Table::create()
+                    .table(WatchRule::Table)
+                    .if_not_exists()
+                    .col(pk_auto(WatchRule::Id))
+                    .col(integer(WatchRule::InfoId))
+                    .col(big_integer_null(WatchRule::ReferralId))
+                    .col(big_integer_null(WatchRule::DoctorId))
+                    .col(text_null(WatchRule::DoctorName))
+                    .col(big_integer_null(WatchRule::LpuId))
+                    .col(date_null(WatchRule::DateFrom))
+                    .col(date_null(WatchRule::DateTo))
+                    .col(date_time(WatchRule::CreatedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(WatchRule::Table, WatchRule::InfoId)
+                            .to(Info::Table, Info::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .to_owned()
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .drop_table(Table::drop().table(WatchRule::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,67 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum ReferralReminder {
+    Table,
+    Id,
+    InfoId,
+    ReferralId,
+    DaysBefore,
+    SentAt,
+}
+
+#[derive(DeriveIden)]
+enum Info {
+    Table,
+    Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                sea_query::Table::create()
+                    .table(ReferralReminder::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ReferralReminder::Id))
+                    .col(integer(ReferralReminder::InfoId))
+                    .col(big_integer(ReferralReminder::ReferralId))
+                    .col(integer(ReferralReminder::DaysBefore))
+                    .col(date_time(ReferralReminder::SentAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ReferralReminder::Table, ReferralReminder::InfoId)
+                            .to(Info::Table, Info::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .to_owned()
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_referral_reminder_info_referral_days")
+                    .table(ReferralReminder::Table)
+                    .col(ReferralReminder::InfoId)
+                    .col(ReferralReminder::ReferralId)
+                    .col(ReferralReminder::DaysBefore)
+                    .unique()
+                    .to_owned()
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .drop_table(Table::drop().table(ReferralReminder::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,79 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum AppointmentReminder {
+    Table,
+    Id,
+    InfoId,
+    AppointmentId,
+    Kind,
+    Title,
+    StartTime,
+    RoomNumber,
+    Address,
+    LpuShortName,
+    RemindAt,
+    SentAt,
+}
+
+#[derive(DeriveIden)]
+enum Info {
+    Table,
+    Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                sea_query::Table::create()
+                    .table(AppointmentReminder::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AppointmentReminder::Id))
+                    .col(integer(AppointmentReminder::InfoId))
+                    .col(big_integer(AppointmentReminder::AppointmentId))
+                    .col(text(AppointmentReminder::Kind))
+                    .col(text_null(AppointmentReminder::Title))
+                    .col(timestamp_with_time_zone(AppointmentReminder::StartTime))
+                    .col(text_null(AppointmentReminder::RoomNumber))
+                    .col(text_null(AppointmentReminder::Address))
+                    .col(text_null(AppointmentReminder::LpuShortName))
+                    .col(date_time(AppointmentReminder::RemindAt))
+                    .col(date_time_null(AppointmentReminder::SentAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(AppointmentReminder::Table, AppointmentReminder::InfoId)
+                            .to(Info::Table, Info::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .to_owned()
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_appointment_reminder_info_appointment_kind")
+                    .table(AppointmentReminder::Table)
+                    .col(AppointmentReminder::InfoId)
+                    .col(AppointmentReminder::AppointmentId)
+                    .col(AppointmentReminder::Kind)
+                    .unique()
+                    .to_owned()
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+
+        manager
+            .drop_table(Table::drop().table(AppointmentReminder::Table).to_owned())
+            .await
+    }
+}
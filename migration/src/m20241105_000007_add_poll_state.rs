@@ -0,0 +1,55 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Info {
+    Table,
+    LastPolledAt,
+    NextPollAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite не умеет добавлять несколько колонок одним ALTER TABLE.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Info::Table)
+                    .add_column(date_time_null(Info::LastPolledAt))
+                    .to_owned()
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Info::Table)
+                    .add_column(date_time_null(Info::NextPollAt))
+                    .to_owned()
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Info::Table)
+                    .drop_column(Info::NextPollAt)
+                    .to_owned()
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Info::Table)
+                    .drop_column(Info::LastPolledAt)
+                    .to_owned()
+            )
+            .await
+    }
+}
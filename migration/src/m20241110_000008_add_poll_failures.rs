@@ -0,0 +1,58 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Info {
+    Table,
+    FailureCount,
+    LastError,
+    LastFailedAt,
+    FailureNotified,
+    PollingPaused,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite не умеет добавлять несколько колонок одним ALTER TABLE.
+        let columns = [
+            integer(Info::FailureCount).default(0).to_owned(),
+            text_null(Info::LastError).to_owned(),
+            date_time_null(Info::LastFailedAt).to_owned(),
+            boolean(Info::FailureNotified).default(false).to_owned(),
+            boolean(Info::PollingPaused).default(false).to_owned(),
+        ];
+
+        for mut column in columns {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Info::Table)
+                        .add_column(&mut column)
+                        .to_owned()
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let columns = [Info::FailureCount, Info::LastError, Info::LastFailedAt, Info::FailureNotified, Info::PollingPaused];
+
+        for column in columns {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Info::Table)
+                        .drop_column(column)
+                        .to_owned()
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Строки `info` становятся профилями пациентов: у одного чата может быть несколько
+/// профилей (ребёнок, родители), один из них активный.
+#[derive(DeriveIden)]
+enum Info {
+    Table,
+    ChatId,
+    Name,
+    IsActive,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Info::Table)
+                    .add_column(text_null(Info::Name))
+                    .to_owned()
+            )
+            .await?;
+
+        // Уже существующие записи — единственный и потому активный профиль своего чата.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Info::Table)
+                    .add_column(boolean(Info::IsActive).default(true))
+                    .to_owned()
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_info_chat_id")
+                    .table(Info::Table)
+                    .col(Info::ChatId)
+                    .to_owned()
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(Index::drop().name("idx_info_chat_id").table(Info::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Info::Table)
+                    .drop_column(Info::IsActive)
+                    .to_owned()
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Info::Table)
+                    .drop_column(Info::Name)
+                    .to_owned()
+            )
+            .await
+    }
+}
@@ -0,0 +1,53 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Отметка, что ЕМИАС узнал пациента по полису и дате рождения профиля.
+#[derive(DeriveIden)]
+enum Info {
+    Table,
+    OmsCard,
+    DateBirth,
+    PollingPaused,
+    Verified,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Info::Table)
+                    .add_column(boolean(Info::Verified).default(false))
+                    .to_owned()
+            )
+            .await?;
+
+        // Заполненные профили, которые уже опрашиваются, считаем проверенными, иначе после
+        // обновления бот перестанет следить за ними до повторного ввода данных.
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Info::Table)
+                    .value(Info::Verified, true)
+                    .and_where(Expr::col(Info::OmsCard).is_not_null())
+                    .and_where(Expr::col(Info::DateBirth).is_not_null())
+                    .and_where(Expr::col(Info::PollingPaused).eq(false))
+                    .to_owned()
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Info::Table)
+                    .drop_column(Info::Verified)
+                    .to_owned()
+            )
+            .await
+    }
+}
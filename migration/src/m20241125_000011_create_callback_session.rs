@@ -0,0 +1,47 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Токены кнопок, данные которых не помещаются в 64 байта `callback_data`. Таблица нужна,
+/// только если хранилище сессий работает с базой (`CALLBACK_SESSION_DB=1`).
+#[derive(DeriveIden)]
+enum CallbackSession {
+    Table,
+    Token,
+    Data,
+    ExpiresAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                sea_query::Table::create()
+                    .table(CallbackSession::Table)
+                    .if_not_exists()
+                    .col(string(CallbackSession::Token).primary_key())
+                    .col(text(CallbackSession::Data))
+                    .col(date_time(CallbackSession::ExpiresAt))
+                    .to_owned()
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_callback_session_expires_at")
+                    .table(CallbackSession::Table)
+                    .col(CallbackSession::ExpiresAt)
+                    .to_owned()
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CallbackSession::Table).to_owned())
+            .await
+    }
+}
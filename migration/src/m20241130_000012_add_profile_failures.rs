@@ -0,0 +1,37 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Счётчик отказов ЕМИАС из-за данных пациента подряд. `failure_count` считает все неудачи
+/// (для задержки), а пауза опроса зависит только от этого счётчика.
+#[derive(DeriveIden)]
+enum Info {
+    Table,
+    ProfileFailureCount,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Info::Table)
+                    .add_column(integer(Info::ProfileFailureCount).default(0))
+                    .to_owned()
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Info::Table)
+                    .drop_column(Info::ProfileFailureCount)
+                    .to_owned()
+            )
+            .await
+    }
+}
@@ -0,0 +1,33 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Планировщик опроса на каждом шаге выбирает пользователей, чья очередь подошла, по
+/// `next_poll_at`.
+#[derive(DeriveIden)]
+enum Info {
+    Table,
+    NextPollAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_info_next_poll_at")
+                    .table(Info::Table)
+                    .col(Info::NextPollAt)
+                    .to_owned()
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(Index::drop().name("idx_info_next_poll_at").table(Info::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,343 @@
+use std::{collections::HashSet, env};
+
+use chrono::{DateTime, Duration, FixedOffset, NaiveTime};
+use sea_orm::{prelude::*, ActiveValue};
+use teloxide::prelude::*;
+use tokio::sync::watch;
+
+use crate::entities::{appointment_reminder, info, prelude::*};
+use crate::parsable::{appointments::AppointmentInfo, doctors::Room};
+use crate::profiles::label;
+use crate::DB;
+
+/// Во сколько (по времени поликлиники) накануне приёма приходит вечернее напоминание.
+const EVENING_TIME: (u32, u32) = (19, 0);
+
+/// За сколько часов до приёма напомнить ещё раз, `APPOINTMENT_REMINDER_HOURS` (по умолчанию 2).
+fn hours_before() -> i64 {
+    env::var("APPOINTMENT_REMINDER_HOURS").ok().and_then(|v| v.parse().ok()).unwrap_or(2)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReminderKind {
+    Evening,
+    Hours
+}
+
+impl ReminderKind {
+    const ALL: [ReminderKind; 2] = [ReminderKind::Evening, ReminderKind::Hours];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            ReminderKind::Evening => "evening",
+            ReminderKind::Hours => "hours"
+        }
+    }
+
+    fn remind_at(&self, start_time: &DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self {
+            ReminderKind::Evening => {
+                let evening = NaiveTime::from_hms_opt(EVENING_TIME.0, EVENING_TIME.1, 0).unwrap();
+                let day_before = start_time.date_naive() - Duration::days(1);
+                day_before.and_time(evening).and_local_timezone(start_time.timezone()).unwrap()
+            },
+            ReminderKind::Hours => *start_time - Duration::hours(hours_before())
+        }
+    }
+}
+
+/// Данные приёма для напоминания.
+#[derive(Debug, Clone)]
+pub struct Visit {
+    pub appointment_id: u64,
+    pub title: Option<String>,
+    pub start_time: DateTime<FixedOffset>,
+    pub room_number: Option<String>,
+    pub address: Option<String>,
+    pub lpu_short_name: Option<String>
+}
+
+impl Visit {
+    /// Приём, на который только что записал бот: место берём из кабинета ЕМИАС.
+    pub fn booked(appointment_id: u64, start_time: DateTime<FixedOffset>, room: Option<&Room>) -> Self {
+        Self {
+            appointment_id,
+            title: None,
+            start_time,
+            room_number: room.map(|r| r.number.clone()),
+            address: room.map(|r| r.default_address.clone()),
+            lpu_short_name: room.map(|r| r.lpu_short_name.clone())
+        }
+    }
+}
+
+impl From<&AppointmentInfo> for Visit {
+    fn from(appointment: &AppointmentInfo) -> Self {
+        Self {
+            appointment_id: appointment.id,
+            title: Some(appointment.name()),
+            start_time: appointment.start_time,
+            room_number: appointment.room_number.clone(),
+            address: appointment.lpu_address.clone(),
+            lpu_short_name: appointment.lpu_short_name.clone()
+        }
+    }
+}
+
+/// Заводит напоминания о приёме. Уже существующие напоминания дополняются недостающими
+/// данными, а если время приёма изменилось — пересоздаются.
+pub async fn schedule(user_id: i32, visit: &Visit) -> Result<(), DbErr> {
+    let db = DB.get().unwrap();
+    let now = chrono::Utc::now().naive_utc();
+
+    let existing = AppointmentReminder::find()
+        .filter(appointment_reminder::Column::InfoId.eq(user_id))
+        .filter(appointment_reminder::Column::AppointmentId.eq(visit.appointment_id as i64))
+        .all(db)
+        .await?;
+
+    for kind in ReminderKind::ALL {
+        let remind_at = kind.remind_at(&visit.start_time).naive_utc();
+
+        match existing.iter().find(|row| row.kind == kind.as_str()) {
+            Some(row) if row.start_time == visit.start_time && row.title.is_none() && visit.title.is_some() => {
+                let mut active: appointment_reminder::ActiveModel = row.clone().into();
+                active.title = ActiveValue::Set(visit.title.clone());
+                active.room_number = ActiveValue::Set(row.room_number.clone().or(visit.room_number.clone()));
+                active.address = ActiveValue::Set(row.address.clone().or(visit.address.clone()));
+                active.lpu_short_name = ActiveValue::Set(row.lpu_short_name.clone().or(visit.lpu_short_name.clone()));
+                active.update(db).await?;
+            },
+            Some(row) if row.start_time == visit.start_time => {},
+            Some(row) => {
+                let mut row: appointment_reminder::ActiveModel = row.clone().into();
+                row.start_time = ActiveValue::Set(visit.start_time);
+                row.remind_at = ActiveValue::Set(remind_at);
+                row.sent_at = ActiveValue::Set(None);
+                row.update(db).await?;
+            },
+            None if remind_at > now => {
+                AppointmentReminder::insert(appointment_reminder::ActiveModel {
+                    info_id: ActiveValue::Set(user_id),
+                    appointment_id: ActiveValue::Set(visit.appointment_id as i64),
+                    kind: ActiveValue::Set(kind.as_str().to_string()),
+                    title: ActiveValue::Set(visit.title.clone()),
+                    start_time: ActiveValue::Set(visit.start_time),
+                    room_number: ActiveValue::Set(visit.room_number.clone()),
+                    address: ActiveValue::Set(visit.address.clone()),
+                    lpu_short_name: ActiveValue::Set(visit.lpu_short_name.clone()),
+                    remind_at: ActiveValue::Set(remind_at),
+                    sent_at: ActiveValue::Set(None),
+                    ..Default::default()
+                }).exec(db).await?;
+            },
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Удаляет напоминания об отменённом или перенесённом приёме.
+pub async fn forget(user_id: i32, appointment_id: u64) -> Result<(), DbErr> {
+    AppointmentReminder::delete_many()
+        .filter(appointment_reminder::Column::InfoId.eq(user_id))
+        .filter(appointment_reminder::Column::AppointmentId.eq(appointment_id as i64))
+        .exec(DB.get().unwrap())
+        .await?;
+
+    Ok(())
+}
+
+/// Сверяет напоминания с записями пользователя в ЕМИАС: заводит напоминания о записях,
+/// сделанных не через бота, и удаляет напоминания о записях, которых больше нет.
+pub async fn sync(user: &info::Model, appointments: &[AppointmentInfo]) -> Result<(), DbErr> {
+    for appointment in appointments {
+        schedule(user.id, &Visit::from(appointment)).await?;
+    }
+
+    let current = appointments.iter().map(|a| a.id as i64).collect::<HashSet<i64>>();
+    let stale = AppointmentReminder::find()
+        .filter(appointment_reminder::Column::InfoId.eq(user.id))
+        .all(DB.get().unwrap())
+        .await?
+        .into_iter()
+        .filter(|row| !current.contains(&row.appointment_id))
+        .map(|row| row.id)
+        .collect::<Vec<i32>>();
+
+    if !stale.is_empty() {
+        AppointmentReminder::delete_many()
+            .filter(appointment_reminder::Column::Id.is_in(stale))
+            .exec(DB.get().unwrap())
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Раз в минуту отправляет наступившие напоминания. Напоминания хранятся в базе, поэтому
+/// переживают перезапуск бота; о приёмах, которые уже начались, не напоминаем.
+pub async fn run(bot: Bot, mut shutdown: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(60));
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Err(err) = send_due(&bot).await {
+                    log::error!("Failed to send appointment reminders: {}", err);
+                }
+            },
+            _ = shutdown.changed() => break
+        }
+    }
+}
+
+async fn send_due(bot: &Bot) -> Result<(), DbErr> {
+    let db = DB.get().unwrap();
+    let now = chrono::Utc::now();
+
+    let due = AppointmentReminder::find()
+        .filter(appointment_reminder::Column::SentAt.is_null())
+        .filter(appointment_reminder::Column::RemindAt.lte(now.naive_utc()))
+        .find_also_related(Info)
+        .all(db)
+        .await?;
+
+    for (reminder, user) in due {
+        if let Some(user) = user {
+            if reminder.start_time > now {
+                let sent = bot.send_message(ChatId(user.chat_id), label(&user, collect_reminder_text(&reminder, &now))).await;
+                if let Err(err) = sent {
+                    log::warn!("Failed to send appointment reminder to chat {}: {}", user.chat_id, err);
+                }
+            }
+        }
+
+        let mut reminder: appointment_reminder::ActiveModel = reminder.into();
+        reminder.sent_at = ActiveValue::Set(Some(now.naive_utc()));
+        reminder.update(db).await?;
+    }
+
+    Ok(())
+}
+
+fn collect_reminder_text(reminder: &appointment_reminder::Model, now: &DateTime<chrono::Utc>) -> String {
+    let today = now.with_timezone(&reminder.start_time.timezone()).date_naive();
+    let day = match (reminder.start_time.date_naive() - today).num_days() {
+        0 => "Сегодня".to_string(),
+        1 => "Завтра".to_string(),
+        _ => reminder.start_time.format("%d.%m.%Y").to_string()
+    };
+    let when = format!("{} в {}", day, reminder.start_time.format("%H:%M"));
+
+    let mut text = match &reminder.title {
+        Some(title) => format!("Напоминание: {} — {}.\n", when, title),
+        None => format!("Напоминание: {} у вас приём.\n", when)
+    };
+
+    if let Some(lpu) = &reminder.lpu_short_name {
+        text.push_str(&format!("Поликлиника: {}\n", lpu));
+    }
+    if let Some(room) = &reminder.room_number {
+        text.push_str(&format!("Кабинет: {}\n", room));
+    }
+    if let Some(address) = &reminder.address {
+        text.push_str(&format!("Адрес: {}\n", address));
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::SubsecRound;
+
+    use super::*;
+    use crate::test_support::{block_on, insert_profile};
+
+    fn moscow(text: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(text).unwrap()
+    }
+
+    /// Приём через `hours` часов от текущего момента по московскому времени.
+    fn visit(appointment_id: u64, hours: i64) -> Visit {
+        let now = chrono::Utc::now().with_timezone(&FixedOffset::east_opt(3 * 3600).unwrap()).trunc_subsecs(0);
+        Visit::booked(appointment_id, now + Duration::hours(hours), None)
+    }
+
+    async fn reminders(user: &info::Model) -> Vec<appointment_reminder::Model> {
+        AppointmentReminder::find()
+            .filter(appointment_reminder::Column::InfoId.eq(user.id))
+            .all(DB.get().unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn remind_at_is_evening_before_and_hours_before() {
+        let start = moscow("2024-09-20T08:00:00+03:00");
+        assert_eq!(ReminderKind::Evening.remind_at(&start), moscow("2024-09-19T19:00:00+03:00"));
+        assert_eq!(ReminderKind::Hours.remind_at(&start), moscow("2024-09-20T06:00:00+03:00"));
+    }
+
+    #[test]
+    fn both_reminders_are_scheduled_once() {
+        block_on(async {
+            let user = insert_profile(113).await;
+            let visit = visit(1, 72);
+
+            schedule(user.id, &visit).await.unwrap();
+            schedule(user.id, &visit).await.unwrap();
+
+            let rows = reminders(&user).await;
+            assert_eq!(rows.len(), 2);
+            for kind in ReminderKind::ALL {
+                let row = rows.iter().find(|row| row.kind == kind.as_str()).unwrap();
+                assert_eq!(row.remind_at, kind.remind_at(&visit.start_time).naive_utc());
+            }
+        });
+    }
+
+    #[test]
+    fn passed_reminders_are_skipped() {
+        block_on(async {
+            let user = insert_profile(114).await;
+
+            // Вечер накануне уже прошёл, до напоминания за 2 часа ещё есть время.
+            schedule(user.id, &visit(1, 4)).await.unwrap();
+            let kinds = reminders(&user).await.into_iter().map(|row| row.kind).collect::<Vec<String>>();
+            assert_eq!(kinds, ["hours"]);
+
+            // Приём вот-вот начнётся — напоминать уже поздно.
+            schedule(user.id, &visit(2, 1)).await.unwrap();
+            assert_eq!(reminders(&user).await.len(), 1);
+        });
+    }
+
+    #[test]
+    fn moved_appointment_is_rescheduled() {
+        block_on(async {
+            let user = insert_profile(115).await;
+            schedule(user.id, &visit(1, 72)).await.unwrap();
+
+            // Вечернее напоминание уже отправлено, а потом приём перенесли на сутки.
+            let evening = reminders(&user).await.into_iter().find(|row| row.kind == "evening").unwrap();
+            let mut sent: appointment_reminder::ActiveModel = evening.into();
+            sent.sent_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
+            sent.update(DB.get().unwrap()).await.unwrap();
+
+            let moved = visit(1, 96);
+            schedule(user.id, &moved).await.unwrap();
+
+            let rows = reminders(&user).await;
+            assert_eq!(rows.len(), 2);
+            for row in rows {
+                let kind = ReminderKind::ALL.into_iter().find(|kind| kind.as_str() == row.kind).unwrap();
+                assert_eq!(row.start_time, moved.start_time);
+                assert_eq!(row.remind_at, kind.remind_at(&moved.start_time).naive_utc());
+                assert_eq!(row.sent_at, None);
+            }
+        });
+    }
+}
@@ -0,0 +1,165 @@
+use std::{collections::HashSet, env};
+
+use sea_orm::{prelude::*, ActiveValue};
+use teloxide::{prelude::*, types::InlineKeyboardMarkup};
+
+use crate::em_commands::callback_data::{profile_button, CallbackAction};
+use crate::entities::{info, watch_rule};
+use crate::error::EmiasError;
+use crate::helper::{collect_appointment_data, find_room, get_schedule_obj, FreeRoom, ReferralAvailability, ScheduleTarget};
+use crate::parsable::{appointments::AppointmentInfo, schedule::Slot};
+use crate::appointment_reminders::{self, Visit};
+use crate::profiles::label;
+use crate::{oms, DB, EMIAS};
+
+/// Пробный режим автозаписи (`AUTO_BOOK_DRY_RUN=1`): бот находит слот и сообщает о нём,
+/// но не записывает пациента. Автозапись в правиле после этого выключается так же, как после
+/// настоящей записи, иначе отчёт повторялся бы при каждом опросе.
+fn dry_run() -> bool {
+    env::var("AUTO_BOOK_DRY_RUN").is_ok_and(|v| matches!(v.as_str(), "1" | "true" | "yes"))
+}
+
+/// Записывает пользователя на первое подходящее время по правилам с включённой автозаписью.
+///
+/// Ограничения: не больше одной автозаписи на направление, и ничего не делаем, если по
+/// направлению уже есть запись — её не перезаписываем. После успешной записи автозапись
+/// в правиле выключается, чтобы отмена записи не приводила к новой.
+///
+/// Возвращает `true`, если пациент записан хотя бы по одному правилу: список записей,
+/// полученный до автозаписи, после этого устарел.
+pub async fn run(bot: &Bot, user: &info::Model, rules: &[watch_rule::Model], referrals: &[ReferralAvailability], appointments: &[AppointmentInfo]) -> bool {
+    let mut booked = false;
+    let mut seen_referrals = HashSet::new();
+    let auto_rules = rules.iter()
+        .filter(|rule| rule.auto_book)
+        .filter(|rule| rule.referral_id.is_some_and(|id| seen_referrals.insert(id)))
+        .collect::<Vec<&watch_rule::Model>>();
+
+    for rule in auto_rules {
+        let referral_id = rule.referral_id.unwrap() as u64;
+
+        if appointments.iter().any(|a| a.referral_id == Some(referral_id)) {
+            continue;
+        }
+
+        let Some(referral) = referrals.iter().find(|r| r.referral_id == referral_id) else {
+            continue;
+        };
+
+        let mut rooms = referral.rooms.iter()
+            .filter(|room| rule.matches(referral_id, room))
+            .collect::<Vec<&FreeRoom>>();
+        rooms.sort_by_key(|room| room.date);
+
+        for room in rooms {
+            let target = ScheduleTarget {
+                referral_id,
+                resource_id: room.resource_id,
+                complex_resource_id: room.complex_resource_id
+            };
+
+            match book_first_slot(bot, user, rule, &target).await {
+                Ok(true) => {
+                    booked |= !dry_run();
+                    break;
+                },
+                Ok(false) => continue,
+                Err(err) => {
+                    log::warn!("Auto-book for chat {} failed: {}", user.chat_id, err);
+                    break;
+                }
+            }
+        }
+    }
+
+    booked
+}
+
+/// Пытается записаться на первый подходящий слот кабинета. `Ok(false)` — свободного
+/// подходящего времени уже нет, можно пробовать следующий кабинет.
+async fn book_first_slot(bot: &Bot, user: &info::Model, rule: &watch_rule::Model, target: &ScheduleTarget) -> Result<bool, EmiasError> {
+    let schedule = get_schedule_obj(user, target).await?;
+
+    let slots = schedule.result.schedule_of_day
+        .into_iter()
+        .filter(|day| rule.matches_date(day.date))
+        .flat_map(|day| day.schedule_by_slot)
+        .flat_map(|by_slot| by_slot.slot)
+        .collect::<Vec<Slot>>();
+
+    let Some(slot) = slots.into_iter().min_by_key(|slot| slot.start_time) else {
+        return Ok(false);
+    };
+
+    let room = find_room(user, &target.referral_id, &target.complex_resource_id).await.ok().flatten();
+
+    if dry_run() {
+        disable_auto_book(rule).await;
+
+        let sent = bot.send_message(
+            ChatId(user.chat_id),
+            label(user, format!(
+                "Автозапись (пробный режим) по правилу #{}: нашлось подходящее время, запись не создана. \n{}Автозапись по этому правилу выключена.",
+                rule.id, collect_appointment_data(&slot, room.as_ref())
+            ))
+        ).await;
+        if let Err(err) = sent {
+            log::error!("Failed to send auto-book dry-run report to chat {}: {}", user.chat_id, err);
+        }
+        return Ok(true);
+    }
+
+    let booked = EMIAS.get().unwrap().create_appointment(
+        &oms::format(user.oms_card.unwrap()),
+        &user.date_birth.unwrap(),
+        target.resource_id,
+        target.complex_resource_id,
+        target.referral_id,
+        &slot.start_time,
+        &slot.end_time
+    ).await;
+
+    let created = match booked {
+        Ok(created) => created.result,
+        Err(err) if err.is_slot_unavailable() => {
+            log::info!("Auto-book slot for chat {} is already taken: {}", user.chat_id, err);
+            return Ok(false);
+        },
+        Err(err) => return Err(err)
+    };
+
+    let visit = Visit::booked(created.appointment_id, slot.start_time, room.as_ref());
+    if let Err(err) = appointment_reminders::schedule(user.id, &visit).await {
+        log::error!("Failed to schedule appointment reminders for chat {}: {}", user.chat_id, err);
+    }
+
+    disable_auto_book(rule).await;
+
+    let cancel_key = profile_button(
+        "Отменить запись",
+        user.id,
+        CallbackAction::CancelAppointmentOk { appointment_id: created.appointment_id }
+    );
+    let markup = InlineKeyboardMarkup::new([[cancel_key]]);
+
+    let sent = bot.send_message(
+        ChatId(user.chat_id),
+        label(user, format!(
+            "Автозапись по правилу #{}: вы записаны! \n{}Автозапись по этому правилу выключена.",
+            rule.id, collect_appointment_data(&slot, room.as_ref())
+        ))
+    ).reply_markup(markup).await;
+    if let Err(err) = sent {
+        log::error!("Failed to send auto-book report to chat {}: {}", user.chat_id, err);
+    }
+
+    Ok(true)
+}
+
+async fn disable_auto_book(rule: &watch_rule::Model) {
+    let mut active: watch_rule::ActiveModel = rule.clone().into();
+    active.auto_book = ActiveValue::Set(false);
+    if let Err(err) = active.update(DB.get().unwrap()).await {
+        log::error!("Failed to disable auto-book rule {}: {}", rule.id, err);
+    }
+}
@@ -0,0 +1,22 @@
+use std::{env, error::Error};
+
+use em_bot::mock::{MockEmias, Scenario};
+
+/// Локальный мок ЕМИАС: `cargo run --features mock --bin mock_emias -- <scenario.json> [addr]`.
+/// Бот подключается к нему через `EMIAS_URL=http://<addr>/`.
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    pretty_env_logger::init();
+
+    let mut args = env::args().skip(1);
+    let scenario_path = args.next().ok_or("Usage: mock_emias <scenario.json> [addr]")?;
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:8089".to_string());
+
+    let scenario = Scenario::from_file(&scenario_path)?;
+    let server = MockEmias::bind(&addr, scenario).await?;
+    log::info!("Mock EMIAS serves {} at {}", scenario_path, server.url());
+
+    server.serve_forever().await;
+
+    Ok(())
+}
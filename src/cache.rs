@@ -0,0 +1,54 @@
+use std::{collections::HashMap, sync::Mutex, time::{Duration, Instant}};
+
+use chrono::NaiveDate;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub oms_number: String,
+    pub birth_date: NaiveDate,
+    pub method: &'static str,
+    pub referral_id: Option<u64>,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    expires_at: Instant,
+    body: Vec<u8>,
+}
+
+/// Кэш тел ответов ЕМИАС в памяти процесса с отдельным TTL на каждую запись.
+///
+/// Хранятся сырые тела ответов, а не разобранные структуры: так кэшу не нужно знать
+/// о типах конкретных методов.
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+}
+
+impl ResponseCache {
+    pub fn get(&self, key: &CacheKey) -> Option<Vec<u8>> {
+        let entries = self.entries.lock().unwrap();
+
+        entries.get(key)
+            .filter(|entry| entry.expires_at > Instant::now())
+            .map(|entry| entry.body.clone())
+    }
+
+    pub fn insert(&self, key: CacheKey, body: Vec<u8>, ttl: Duration) {
+        if ttl.is_zero() {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+
+        entries.retain(|_, entry| entry.expires_at > now);
+        entries.insert(key, CacheEntry { expires_at: now + ttl, body });
+    }
+
+    /// Сбрасывает все записи пациента, например по кнопке «Обновить».
+    pub fn invalidate(&self, oms_number: &str, birth_date: &NaiveDate) {
+        self.entries.lock().unwrap()
+            .retain(|key, _| key.oms_number != oms_number || key.birth_date != *birth_date);
+    }
+}
@@ -1,86 +1,754 @@
-use crate::{entities::info::Model, helper::{get_doctors_obj, get_referrals_obj}, parsable::doctors};
-use teloxide::{prelude::*, types::{InlineKeyboardButton, InlineKeyboardMarkup, MessageId}};
+use crate::{
+    appointment_reminders::{self, Visit}, 
+    em_commands::{callback_data::{button, CallbackAction}, pagination::PaginatedKeyboard}, 
+    entities::info::Model, 
+    helper::{
+        collect_appointment_data, collect_appointment_info, collect_appointments_data, collect_schedule_data, find_appointment, find_room, find_slot, find_slot_in, 
+        get_appointment_schedule_obj, get_schedule_obj, referral_name, ScheduleTarget
+    }, 
+    message_builder::{edit_parts, MessageBuilder}, 
+    oms, 
+    parsable::{appointments::AppointmentInfo, doctors::{self, HasComplexResource}, schedule::{ScheduleInfo, Slot}}, 
+    poller::hold_for_verification, 
+    profiles::{chat_profile, chat_profiles, collect_profiles_data, display_name, profiles_markup, remove_profile, switch_profile}, 
+    verification::verify_and_report, 
+    watch::collect_watch_rules_data, 
+    DB, EMIAS
+};
+use crate::entities::{info, prelude::*, watch_rule};
+use sea_orm::{prelude::*, ActiveValue};
+use chrono::{NaiveDate, NaiveDateTime};
+use std::{collections::{hash_map::Entry, HashMap}, sync::Mutex, time::{Duration, Instant}};
+use teloxide::{prelude::*, types::{InlineKeyboardMarkup, MessageId}};
 
-pub async fn get_referrals(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId) {
-    let refs_result = get_referrals_obj(&user).await;
+/// Кнопок времени в одной строке на экранах выбора времени.
+const SLOTS_PER_ROW: usize = 4;
+/// Строк кнопок времени на одной странице.
+const SLOT_PAGE_ROWS: usize = 6;
+
+/// Сколько помнить нажатую кнопку подтверждения записи или переноса.
+const CONFIRM_MEMORY: Duration = Duration::from_secs(10*60);
+
+lazy_static::lazy_static! {
+    /// Нажатые «Подтвердить» и «Подтвердить перенос»: сообщение и выбранное время.
+    static ref CONFIRMED: Mutex<HashMap<(ChatId, MessageId, NaiveDateTime), Instant>> = Mutex::new(HashMap::new());
+}
+
+/// Отмечает нажатие кнопки подтверждения. `false` — эту кнопку уже нажимали: повторное
+/// нажатие Telegram присылает отдельным колбэком, и без отметки бот записал бы пациента
+/// второй раз (или сообщил бы, что время занято — им же самим).
+fn claim_confirm(chat_id: ChatId, message_id: MessageId, start: &NaiveDateTime) -> bool {
+    let now = Instant::now();
+    let mut confirmed = CONFIRMED.lock().unwrap();
+    confirmed.retain(|_, pressed_at| now.duration_since(*pressed_at) < CONFIRM_MEMORY);
+
+    match confirmed.entry((chat_id, message_id, *start)) {
+        Entry::Occupied(_) => false,
+        Entry::Vacant(entry) => {
+            entry.insert(now);
+            true
+        }
+    }
+}
+
+/// Снимает отметку, если записаться не удалось: кнопку можно нажать ещё раз.
+fn release_confirm(chat_id: ChatId, message_id: MessageId, start: &NaiveDateTime) {
+    CONFIRMED.lock().unwrap().remove(&(chat_id, message_id, *start));
+}
+
+pub async fn get_referrals(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, page: usize) {
+    let refs_result = EMIAS.get().unwrap().get_referrals_info(&oms::format(user.oms_card.unwrap()), &user.date_birth.unwrap()).await;
     match refs_result {
         Ok(referrals) => {
-            let away_key = InlineKeyboardButton::new("Назад", teloxide::types::InlineKeyboardButtonKind::CallbackData("back_to_main".to_string()));
-            let mut refs_keys = vec![];
-            
-            for referral in referrals.result {
-                let name = if referral.to_doctor.is_some() { referral.to_doctor.unwrap().speciality_name  } else { referral.to_ldp.unwrap().ldp_type_name };
-                refs_keys.push(
-                    [InlineKeyboardButton::new(name, teloxide::types::InlineKeyboardButtonKind::CallbackData(format!("get_doctors/{}", referral.id)))]
-                );
-            }
-
-            refs_keys.push([away_key]);
+            let away_key = button("Назад", CallbackAction::MainMenu);
+            let refresh_key = button("Обновить", CallbackAction::RefreshReferrals);
 
-            let markup = InlineKeyboardMarkup::new(refs_keys);
+            let markup = PaginatedKeyboard::new(
+                    &referrals.result,
+                    |referral| vec![button(referral_name(referral), CallbackAction::Doctors { referral_id: referral.id, page: 0 })]
+                )
+                .empty("Нет направлений.")
+                .footer(vec![refresh_key])
+                .footer(vec![away_key])
+                .build(page, |page| CallbackAction::Referrals { page });
 
-            bot.edit_message_reply_markup(chat_id, message_id).reply_markup(markup).await.unwrap();
+            update_markup(&bot, chat_id, message_id, markup).await;
         },
-        Err(_) => {
-            bot.send_message(chat_id, "Не удалось получить список направлений").await.unwrap();
+        Err(err) => {
+            bot.send_message(chat_id, format!("Не удалось получить список направлений. \n{}", err.user_message())).await.unwrap();
         }
     }
 } 
 
 pub async fn back_to_main(bot: Bot, chat_id:ChatId, message_id:MessageId) {
-    let go_to_ref_button = InlineKeyboardButton::new(
-        "Записаться", 
-        teloxide::types::InlineKeyboardButtonKind::CallbackData("get_referrals".to_string())
-    );
+    let go_to_ref_button = button("Записаться", CallbackAction::Referrals { page: 0 });
     let markup = InlineKeyboardMarkup::new([[go_to_ref_button]]);
     bot.edit_message_reply_markup(chat_id, message_id).reply_markup(markup).await.unwrap();
 }
 
-pub async fn get_doctors(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, referral_id: &u64) {
-    let docs_result = get_doctors_obj(&user, referral_id).await;
+pub async fn get_doctors(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, referral_id: &u64, page: usize) {
+    let docs_result = EMIAS.get().unwrap().get_doctors_info(&oms::format(user.oms_card.unwrap()), &user.date_birth.unwrap(), *referral_id).await;
 
     match docs_result {
         Ok(doctors) => {
-            let away_key=InlineKeyboardButton::new("Назад", teloxide::types::InlineKeyboardButtonKind::CallbackData("get_referrals".to_string()));
-
-            match doctors.result {
-                doctors::ResultType::DocArray(doctors) => {
-                    let mut doc_vec = vec![];
-                    for doctor in doctors {
-                        let doc_button = InlineKeyboardButton::new(
-                            &format!("{} {} {}", doctor.main_doctor.first_name, doctor.main_doctor.second_name, doctor.main_doctor.last_name), 
-                            teloxide::types::InlineKeyboardButtonKind::CallbackData("get_shedule".to_string()));
-                        
-                        doc_vec.push([doc_button]);
-                    }
-
-                    doc_vec.push([away_key]);
-                    let markup = InlineKeyboardMarkup::new(doc_vec);
-                    bot.edit_message_reply_markup(chat_id, message_id).reply_markup(markup).await.unwrap();
-                },
-                doctors::ResultType::LdpArray(ldps) => {
-                    let mut ldp_vec = vec![];
-                    for ldp in ldps {
-                        let ldp_button = InlineKeyboardButton::new(
-                            &format!("{}", ldp.name), 
-                            teloxide::types::InlineKeyboardButtonKind::CallbackData("get_shedule".to_string()));
-                        
-                        ldp_vec.push([ldp_button]);
-                    }
-
-                    ldp_vec.push([away_key]);
-                    let markup = InlineKeyboardMarkup::new(ldp_vec);
-                    bot.edit_message_reply_markup(chat_id, message_id).reply_markup(markup).await.unwrap();
-                },
-                doctors::ResultType::EmptyObject(_) => {
-                    let no_doc = InlineKeyboardButton::new("Нет врачей по данному направлению.", teloxide::types::InlineKeyboardButtonKind::CallbackData("_".to_string()));
-                    let markup = InlineKeyboardMarkup::new([[no_doc], [away_key]]);
-                    bot.edit_message_reply_markup(chat_id, message_id).reply_markup(markup).await.unwrap();
-                }
+            let away_key=button("Назад", CallbackAction::Referrals { page: 0 });
+            let refresh_key = button("Обновить", CallbackAction::RefreshDoctors { referral_id: *referral_id });
+
+            let doc_vec: Vec<(String, CallbackAction)> = match doctors.result {
+                doctors::ResultType::DocArray(doctors) => doctors.iter()
+                    .map(|doctor| (
+                        format!("{} {} {}", doctor.main_doctor.first_name, doctor.main_doctor.second_name, doctor.main_doctor.last_name), 
+                        schedule_callback(doctor, doctor.id, referral_id)
+                    ))
+                    .collect(),
+                doctors::ResultType::LdpArray(ldps) => ldps.iter()
+                    .map(|ldp| (ldp.name.clone(), schedule_callback(ldp, ldp.id, referral_id)))
+                    .collect(),
+                doctors::ResultType::EmptyObject(_) => vec![]
+            };
+
+            let markup = PaginatedKeyboard::new(doc_vec, |(name, action)| vec![button(name, action.clone())])
+                .empty("Нет врачей по данному направлению.")
+                .footer(vec![refresh_key])
+                .footer(vec![away_key])
+                .build(page, |page| CallbackAction::Doctors { referral_id: *referral_id, page });
+            update_markup(&bot, chat_id, message_id, markup).await;
+        },
+        Err(err) => {
+            bot.send_message(chat_id, format!("Не удалось получить список врачей. \n{}", err.user_message())).await.unwrap();
+        }
+    }
+}
+
+pub async fn get_schedule(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, target: &ScheduleTarget, page: usize) {
+    let schedule_result = get_schedule_obj(&user, target).await;
+
+    match schedule_result {
+        Ok(schedule) => {
+            let away_key = button("Назад", CallbackAction::Doctors { referral_id: target.referral_id, page: 0 });
+
+            let watch_key = button(
+                "Следить за этим врачом", 
+                CallbackAction::WatchResource { referral_id: target.referral_id, resource_id: target.resource_id }
+            );
+            let markup = PaginatedKeyboard::new(
+                    free_days(&schedule.result),
+                    |(date, slots_count)| vec![button(
+                        format!("{} ({})", date.format("%d.%m.%Y"), slots_count), 
+                        CallbackAction::Slots { target: *target, date: *date, page: 0 }
+                    )]
+                )
+                .footer(vec![watch_key])
+                .footer(vec![away_key])
+                .build(page, |page| CallbackAction::Schedule { target: *target, page });
+
+            let mut message = MessageBuilder::new();
+            message.push(format!("Свободное время для записи: \n{}", collect_schedule_data(&schedule.result)));
+            let _ = edit_parts(&bot, chat_id, message_id, message.build(), markup).await;
+        },
+        Err(err) => {
+            bot.send_message(chat_id, format!("Не удалось получить расписание. \n{}", err.user_message())).await.unwrap();
+        }
+    }
+}
+
+pub async fn get_slots(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, target: &ScheduleTarget, date: &NaiveDate, page: usize) {
+    let schedule_result = get_schedule_obj(&user, target).await;
+
+    match schedule_result {
+        Ok(schedule) => {
+            let away_key = button("Назад", CallbackAction::Schedule { target: *target, page: 0 });
+
+            let starts = day_starts(&schedule.result, date);
+
+            let markup = PaginatedKeyboard::new(
+                    starts.chunks(SLOTS_PER_ROW),
+                    |row| row.iter().map(|start| button(
+                        start.format("%H:%M").to_string(), 
+                        CallbackAction::ConfirmSlot { target: *target, start: *start }
+                    )).collect()
+                )
+                .page_rows(SLOT_PAGE_ROWS)
+                .empty("Нет свободного времени в этот день.")
+                .footer(vec![away_key])
+                .build(page, |page| CallbackAction::Slots { target: *target, date: *date, page });
+            bot.edit_message_reply_markup(chat_id, message_id).reply_markup(markup).await.unwrap();
+        },
+        Err(err) => {
+            bot.send_message(chat_id, format!("Не удалось получить расписание. \n{}", err.user_message())).await.unwrap();
+        }
+    }
+}
+
+pub async fn confirm_slot(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, target: &ScheduleTarget, start: &NaiveDateTime) {
+    let slot = match find_slot(&user, target, start).await {
+        Ok(Some(slot)) => slot,
+        Ok(None) => {
+            slot_taken(bot, chat_id, message_id, target).await;
+            return;
+        },
+        Err(err) => {
+            bot.send_message(chat_id, format!("Не удалось получить расписание. \n{}", err.user_message())).await.unwrap();
+            return;
+        }
+    };
+    let room = find_room(&user, &target.referral_id, &target.complex_resource_id).await.ok().flatten();
+
+    let confirm_key = button(
+        "Подтвердить", 
+        CallbackAction::BookSlot { target: *target, start: *start }
+    );
+    let away_key = button(
+        "Назад", 
+        CallbackAction::Slots { target: *target, date: slot.start_time.date_naive(), page: 0 }
+    );
+    let markup = InlineKeyboardMarkup::new([[confirm_key], [away_key]]);
+
+    let text = format!("Подтвердите запись: \n{}", collect_appointment_data(&slot, room.as_ref()));
+    bot.edit_message_text(chat_id, message_id, text).reply_markup(markup).await.unwrap();
+}
+
+pub async fn book_slot(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, target: &ScheduleTarget, start: &NaiveDateTime) {
+    if !claim_confirm(chat_id, message_id, start) {
+        log::info!("Ignoring repeated booking confirmation in chat {}", chat_id);
+        return;
+    }
+
+    let slot = match find_slot(&user, target, start).await {
+        Ok(Some(slot)) => slot,
+        Ok(None) => {
+            release_confirm(chat_id, message_id, start);
+            slot_taken(bot, chat_id, message_id, target).await;
+            return;
+        },
+        Err(err) => {
+            release_confirm(chat_id, message_id, start);
+            bot.send_message(chat_id, format!("Не удалось получить расписание. \n{}", err.user_message())).await.unwrap();
+            return;
+        }
+    };
+
+    let booked = EMIAS.get().unwrap().create_appointment(
+        &oms::format(user.oms_card.unwrap()), 
+        &user.date_birth.unwrap(), 
+        target.resource_id, 
+        target.complex_resource_id, 
+        target.referral_id, 
+        &slot.start_time, 
+        &slot.end_time
+    ).await;
+
+    match booked {
+        Ok(created) => {
+            let room = find_room(&user, &target.referral_id, &target.complex_resource_id).await.ok().flatten();
+            let visit = Visit::booked(created.result.appointment_id, slot.start_time, room.as_ref());
+            if let Err(err) = appointment_reminders::schedule(user.id, &visit).await {
+                log::error!("Failed to schedule appointment reminders for chat {}: {}", chat_id, err);
             }
+            let go_to_ref_button = button(
+                "Записаться", 
+                CallbackAction::Referrals { page: 0 }
+            );
+            let markup = InlineKeyboardMarkup::new([[go_to_ref_button]]);
+
+            let text = format!("Вы записаны! \n{}", collect_appointment_data(&slot, room.as_ref()));
+            bot.edit_message_text(chat_id, message_id, text).reply_markup(markup).await.unwrap();
+        },
+        Err(err) if err.is_slot_unavailable() => {
+            log::warn!("createAppointment for chat {} failed: {}", chat_id, err);
+            release_confirm(chat_id, message_id, start);
+            slot_taken(bot, chat_id, message_id, target).await;
+        },
+        Err(err) => {
+            release_confirm(chat_id, message_id, start);
+            bot.send_message(chat_id, format!("Не удалось записаться. \n{}", err.user_message())).await.unwrap();
+        }
+    }
+}
+
+async fn slot_taken(bot: Bot, chat_id:ChatId, message_id:MessageId, target: &ScheduleTarget) {
+    let away_key = button(
+        "Выбрать другое время", 
+        CallbackAction::Schedule { target: *target, page: 0 }
+    );
+    let markup = InlineKeyboardMarkup::new([[away_key]]);
+
+    bot.edit_message_text(
+        chat_id, 
+        message_id, 
+        "Не удалось записаться: выбранное время уже занято или больше недоступно. Выберите другое время."
+    ).reply_markup(markup).await.unwrap();
+}
+
+/// Дни расписания со свободным временем и число свободных слотов в каждом.
+fn free_days(schedule: &ScheduleInfo) -> Vec<(NaiveDate, usize)> {
+    schedule.schedule_of_day
+        .iter()
+        .map(|day| (day.date, day.slots().count()))
+        .filter(|(_, slots_count)| *slots_count > 0)
+        .collect()
+}
+
+/// Начало каждого свободного слота дня `date`.
+fn day_starts(schedule: &ScheduleInfo, date: &NaiveDate) -> Vec<NaiveDateTime> {
+    schedule.schedule_of_day
+        .iter()
+        .filter(|day| day.date == *date)
+        .flat_map(|day| day.slots())
+        .map(|slot| slot.start_time.naive_local())
+        .collect()
+}
+
+fn schedule_callback<T:HasComplexResource>(resource: &T, resource_id: u64, referral_id: &u64) -> CallbackAction {
+    let complex = resource.complex_resource().iter().find(|c| c.room.is_some());
+
+    match complex {
+        Some(c) => CallbackAction::Schedule { target: ScheduleTarget { referral_id: *referral_id, resource_id, complex_resource_id: c.id }, page: 0 },
+        None => CallbackAction::Noop
+    }
+}
+
+pub fn appointments_markup(appointments: &[AppointmentInfo], page: usize) -> InlineKeyboardMarkup {
+    PaginatedKeyboard::new(appointments, |appointment| {
+            let time = appointment.start_time.format("%d.%m %H:%M");
+            vec![
+                button(
+                    format!("Перенести {}", time), 
+                    CallbackAction::ShiftDays { appointment_id: appointment.id, page: 0 }
+                ),
+                button(
+                    format!("Отменить {}", time), 
+                    CallbackAction::CancelAppointment { appointment_id: appointment.id }
+                )
+            ]
+        })
+        .footer(vec![button("Обновить", CallbackAction::Appointments { page: 0 })])
+        .build(page, |page| CallbackAction::Appointments { page })
+}
+
+pub async fn get_appointments(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, page: usize) {
+    let apps_result = EMIAS.get().unwrap().get_appointments_info(&oms::format(user.oms_card.unwrap()), &user.date_birth.unwrap()).await;
+
+    match apps_result {
+        Ok(appointments) => {
+            let markup = appointments_markup(&appointments.result.appointment, page);
+            let text = collect_appointments_data(&appointments.result.appointment);
+            bot.edit_message_text(chat_id, message_id, text).reply_markup(markup).await.unwrap();
+        },
+        Err(err) => {
+            bot.send_message(chat_id, format!("Не удалось получить список записей. \n{}", err.user_message())).await.unwrap();
+        }
+    }
+}
+
+pub async fn confirm_cancel(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, appointment_id: &u64) {
+    let appointment = match find_appointment(&user, appointment_id).await {
+        Ok(Some(appointment)) => appointment,
+        Ok(None) => {
+            appointment_not_found(bot, chat_id, message_id).await;
+            return;
+        },
+        Err(err) => {
+            bot.send_message(chat_id, format!("Не удалось получить список записей. \n{}", err.user_message())).await.unwrap();
+            return;
+        }
+    };
+
+    let confirm_key = button(
+        "Да, отменить", 
+        CallbackAction::CancelAppointmentOk { appointment_id: *appointment_id }
+    );
+    let away_key = button("Назад", CallbackAction::Appointments { page: 0 });
+    let markup = InlineKeyboardMarkup::new([[confirm_key], [away_key]]);
+
+    let text = format!("Отменить запись? \n{}", collect_appointment_info(&appointment));
+    bot.edit_message_text(chat_id, message_id, text).reply_markup(markup).await.unwrap();
+}
+
+pub async fn cancel_appointment(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, appointment_id: &u64) {
+    let cancelled = EMIAS.get().unwrap().cancel_appointment(&oms::format(user.oms_card.unwrap()), &user.date_birth.unwrap(), *appointment_id).await;
+    let away_key = button("К списку записей", CallbackAction::Appointments { page: 0 });
+    let markup = InlineKeyboardMarkup::new([[away_key]]);
+
+    match cancelled {
+        Ok(_) => {
+            if let Err(err) = appointment_reminders::forget(user.id, *appointment_id).await {
+                log::error!("Failed to remove appointment reminders for chat {}: {}", chat_id, err);
+            }
+            bot.edit_message_text(chat_id, message_id, "Запись отменена.").reply_markup(markup).await.unwrap();
+        },
+        Err(err) => {
+            bot.edit_message_text(chat_id, message_id, format!("Не удалось отменить запись. \n{}", err.user_message())).reply_markup(markup).await.unwrap();
+        }
+    }
+}
+
+pub async fn shift_days(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, appointment_id: &u64, page: usize) {
+    let appointment = match find_appointment(&user, appointment_id).await {
+        Ok(Some(appointment)) => appointment,
+        Ok(None) => {
+            appointment_not_found(bot, chat_id, message_id).await;
+            return;
+        },
+        Err(err) => {
+            bot.send_message(chat_id, format!("Не удалось получить список записей. \n{}", err.user_message())).await.unwrap();
+            return;
+        }
+    };
+
+    match get_appointment_schedule_obj(&user, &appointment).await {
+        Ok(schedule) => {
+            let away_key = button("Назад", CallbackAction::Appointments { page: 0 });
+
+            let markup = PaginatedKeyboard::new(
+                    free_days(&schedule.result),
+                    |(date, slots_count)| vec![button(
+                        format!("{} ({})", date.format("%d.%m.%Y"), slots_count), 
+                        CallbackAction::ShiftSlots { appointment_id: *appointment_id, date: *date, page: 0 }
+                    )]
+                )
+                .footer(vec![away_key])
+                .build(page, |page| CallbackAction::ShiftDays { appointment_id: *appointment_id, page });
+
+            let mut message = MessageBuilder::new();
+            message.push(format!(
+                "Перенос записи [{}] {}. \nСвободное время для записи: \n{}", 
+                appointment.start_time.format("%d.%m.%Y %H:%M"), 
+                appointment.name(), 
+                collect_schedule_data(&schedule.result)
+            ));
+            let _ = edit_parts(&bot, chat_id, message_id, message.build(), markup).await;
+        },
+        Err(err) => {
+            bot.send_message(chat_id, format!("Не удалось получить расписание. \n{}", err.user_message())).await.unwrap();
+        }
+    }
+}
+
+pub async fn shift_slots(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, appointment_id: &u64, date: &NaiveDate, page: usize) {
+    let appointment = match find_appointment(&user, appointment_id).await {
+        Ok(Some(appointment)) => appointment,
+        Ok(None) => {
+            appointment_not_found(bot, chat_id, message_id).await;
+            return;
+        },
+        Err(err) => {
+            bot.send_message(chat_id, format!("Не удалось получить список записей. \n{}", err.user_message())).await.unwrap();
+            return;
+        }
+    };
+
+    match get_appointment_schedule_obj(&user, &appointment).await {
+        Ok(schedule) => {
+            let away_key = button("Назад", CallbackAction::ShiftDays { appointment_id: *appointment_id, page: 0 });
+
+            let starts = day_starts(&schedule.result, date);
+
+            let markup = PaginatedKeyboard::new(
+                    starts.chunks(SLOTS_PER_ROW),
+                    |row| row.iter().map(|start| button(
+                        start.format("%H:%M").to_string(), 
+                        CallbackAction::ShiftConfirm { appointment_id: *appointment_id, start: *start }
+                    )).collect()
+                )
+                .page_rows(SLOT_PAGE_ROWS)
+                .empty("Нет свободного времени в этот день.")
+                .footer(vec![away_key])
+                .build(page, |page| CallbackAction::ShiftSlots { appointment_id: *appointment_id, date: *date, page });
+            bot.edit_message_reply_markup(chat_id, message_id).reply_markup(markup).await.unwrap();
+        },
+        Err(err) => {
+            bot.send_message(chat_id, format!("Не удалось получить расписание. \n{}", err.user_message())).await.unwrap();
+        }
+    }
+}
+
+pub async fn confirm_shift(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, appointment_id: &u64, start: &NaiveDateTime) {
+    let Some((appointment, slot)) = find_shift_slot(&bot, &user, chat_id, message_id, appointment_id, start).await else {
+        return;
+    };
+
+    let confirm_key = button(
+        "Подтвердить перенос", 
+        CallbackAction::ShiftOk { appointment_id: *appointment_id, start: *start }
+    );
+    let away_key = button(
+        "Назад", 
+        CallbackAction::ShiftSlots { appointment_id: *appointment_id, date: slot.start_time.date_naive(), page: 0 }
+    );
+    let markup = InlineKeyboardMarkup::new([[confirm_key], [away_key]]);
+
+    let text = format!(
+        "Перенести запись {} с {} на: \n{}", 
+        appointment.name(), 
+        appointment.start_time.format("%d.%m.%Y %H:%M"), 
+        collect_appointment_data(&slot, None)
+    );
+    bot.edit_message_text(chat_id, message_id, text).reply_markup(markup).await.unwrap();
+}
+
+pub async fn shift_appointment(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, appointment_id: &u64, start: &NaiveDateTime) {
+    if !claim_confirm(chat_id, message_id, start) {
+        log::info!("Ignoring repeated shift confirmation in chat {}", chat_id);
+        return;
+    }
+
+    let Some((appointment, slot)) = find_shift_slot(&bot, &user, chat_id, message_id, appointment_id, start).await else {
+        release_confirm(chat_id, message_id, start);
+        return;
+    };
+
+    let shifted = EMIAS.get().unwrap().shift_appointment(
+        &oms::format(user.oms_card.unwrap()), 
+        &user.date_birth.unwrap(), 
+        appointment.id, 
+        appointment.available_resource_id, 
+        appointment.complex_resource_id, 
+        appointment.referral_id, 
+        &slot.start_time, 
+        &slot.end_time
+    ).await;
+
+    let away_key = button("К списку записей", CallbackAction::Appointments { page: 0 });
+    let markup = InlineKeyboardMarkup::new([[away_key]]);
+
+    match shifted {
+        Ok(shifted) => {
+            let visit = Visit {
+                appointment_id: shifted.result.appointment_id,
+                start_time: slot.start_time,
+                ..Visit::from(&appointment)
+            };
+            let rescheduled = match appointment_reminders::forget(user.id, appointment.id).await {
+                Ok(_) => appointment_reminders::schedule(user.id, &visit).await,
+                Err(err) => Err(err)
+            };
+            if let Err(err) = rescheduled {
+                log::error!("Failed to reschedule appointment reminders for chat {}: {}", chat_id, err);
+            }
+
+            let text = format!("Запись перенесена! \n{}", collect_appointment_data(&slot, None));
+            bot.edit_message_text(chat_id, message_id, text).reply_markup(markup).await.unwrap();
+        },
+        Err(err) if err.is_slot_unavailable() => {
+            log::warn!("shiftAppointment for chat {} failed: {}", chat_id, err);
+            release_confirm(chat_id, message_id, start);
+            shift_slot_taken(bot, chat_id, message_id, appointment_id).await;
+        },
+        Err(err) => {
+            release_confirm(chat_id, message_id, start);
+            bot.send_message(chat_id, format!("Не удалось перенести запись. \n{}", err.user_message())).await.unwrap();
+        }
+    }
+}
+
+async fn find_shift_slot(bot: &Bot, user: &Model, chat_id:ChatId, message_id:MessageId, appointment_id: &u64, start: &NaiveDateTime) -> Option<(AppointmentInfo, Slot)> {
+    let appointment = match find_appointment(user, appointment_id).await {
+        Ok(Some(appointment)) => appointment,
+        Ok(None) => {
+            appointment_not_found(bot.clone(), chat_id, message_id).await;
+            return None;
+        },
+        Err(err) => {
+            bot.send_message(chat_id, format!("Не удалось получить список записей. \n{}", err.user_message())).await.unwrap();
+            return None;
+        }
+    };
+
+    match get_appointment_schedule_obj(user, &appointment).await {
+        Ok(schedule) => match find_slot_in(schedule.result, start) {
+            Some(slot) => Some((appointment, slot)),
+            None => {
+                shift_slot_taken(bot.clone(), chat_id, message_id, appointment_id).await;
+                None
+            }
+        },
+        Err(err) => {
+            bot.send_message(chat_id, format!("Не удалось получить расписание. \n{}", err.user_message())).await.unwrap();
+            None
+        }
+    }
+}
+
+async fn shift_slot_taken(bot: Bot, chat_id:ChatId, message_id:MessageId, appointment_id: &u64) {
+    let away_key = button(
+        "Выбрать другое время", 
+        CallbackAction::ShiftDays { appointment_id: *appointment_id, page: 0 }
+    );
+    let markup = InlineKeyboardMarkup::new([[away_key]]);
+
+    bot.edit_message_text(
+        chat_id, 
+        message_id, 
+        "Не удалось перенести запись: выбранное время уже занято или больше недоступно. Выберите другое время."
+    ).reply_markup(markup).await.unwrap();
+}
+
+async fn appointment_not_found(bot: Bot, chat_id:ChatId, message_id:MessageId) {
+    let away_key = button("К списку записей", CallbackAction::Appointments { page: 0 });
+    let markup = InlineKeyboardMarkup::new([[away_key]]);
+
+    bot.edit_message_text(chat_id, message_id, "Запись не найдена: возможно, она уже отменена или перенесена.").reply_markup(markup).await.unwrap();
+}
+
+
+/// Обновляет клавиатуру сообщения. Повторное нажатие «Обновить» без изменений в данных
+/// даёт ошибку Telegram «message is not modified» — её не считаем сбоем.
+async fn update_markup(bot: &Bot, chat_id:ChatId, message_id:MessageId, markup: InlineKeyboardMarkup) {
+    match bot.edit_message_reply_markup(chat_id, message_id).reply_markup(markup).await {
+        Ok(_) | Err(teloxide::RequestError::Api(teloxide::ApiError::MessageNotModified)) => {},
+        Err(err) => log::warn!("Failed to update keyboard of message {} in chat {}: {}", message_id, chat_id, err)
+    }
+}
+
+pub fn watch_rules_markup(rules: &[watch_rule::Model], page: usize) -> InlineKeyboardMarkup {
+    PaginatedKeyboard::new(rules, |rule| vec![button(
+            format!("Удалить #{}", rule.id), 
+            CallbackAction::Unwatch { rule_id: rule.id }
+        )])
+        .build(page, |page| CallbackAction::WatchRules { page })
+}
+
+pub async fn get_watch_rules(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, page: usize) {
+    let rules = WatchRule::find().filter(watch_rule::Column::InfoId.eq(user.id)).all(DB.get().unwrap()).await.unwrap();
+    update_markup(&bot, chat_id, message_id, watch_rules_markup(&rules, page)).await;
+}
+
+pub async fn delete_watch_rule(bot: Bot, user: Model, chat_id:ChatId, message_id:MessageId, rule_id: &i32) {
+    let db = DB.get().unwrap();
+    let deleted = WatchRule::delete_many()
+        .filter(watch_rule::Column::Id.eq(*rule_id))
+        .filter(watch_rule::Column::InfoId.eq(user.id))
+        .exec(db)
+        .await;
+
+    if deleted.is_err() {
+        bot.send_message(chat_id, "Не удалось удалить правило. Попробуйте позже.").await.unwrap();
+        return;
+    }
+
+    let rules = WatchRule::find().filter(watch_rule::Column::InfoId.eq(user.id)).all(db).await.unwrap();
+    bot.edit_message_text(chat_id, message_id, collect_watch_rules_data(&rules)).reply_markup(watch_rules_markup(&rules, 0)).await.unwrap();
+}
+
+pub async fn watch_resource(bot: Bot, user: Model, chat_id:ChatId, referral_id: &u64, resource_id: &u64) {
+    let inserted = WatchRule::insert(watch_rule::ActiveModel {
+        info_id: ActiveValue::Set(user.id),
+        referral_id: ActiveValue::Set(Some(*referral_id as i64)),
+        doctor_id: ActiveValue::Set(Some(*resource_id as i64)),
+        created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+        ..Default::default()
+    }).exec(DB.get().unwrap()).await;
+
+    match inserted {
+        Ok(res) => {
+            bot.send_message(
+                chat_id, 
+                format!("Правило #{} добавлено: бот сообщит, когда у этого врача изменится запись по направлению. Список правил — `/watch`.", res.last_insert_id)
+            ).await.unwrap();
+        },
+        Err(_) => {
+            bot.send_message(chat_id, "Не удалось добавить правило. Попробуйте позже.").await.unwrap();
+        }
+    }
+}
+
+pub async fn get_profiles(bot: Bot, chat_id:ChatId, message_id:MessageId, page: usize) {
+    let profiles = chat_profiles(chat_id.0).await.unwrap();
+    bot.edit_message_text(chat_id, message_id, collect_profiles_data(&profiles)).reply_markup(profiles_markup(&profiles, page)).await.unwrap();
+}
+
+pub async fn use_profile(bot: Bot, chat_id:ChatId, message_id:MessageId, profile_id: &i32) {
+    match switch_profile(chat_id.0, *profile_id).await {
+        Ok(Some(_)) => get_profiles(bot, chat_id, message_id, 0).await,
+        Ok(None) => {
+            bot.send_message(chat_id, "Профиль не найден. Возможно, он уже удалён.").await.unwrap();
         },
         Err(_) => {
-            bot.send_message(chat_id, "Не удалось получить список врачей").await.unwrap();
+            bot.send_message(chat_id, "Не удалось переключить профиль. Попробуйте позже.").await.unwrap();
         }
     }
-}
\ No newline at end of file
+}
+
+pub async fn confirm_remove_profile(bot: Bot, chat_id:ChatId, message_id:MessageId, profile_id: &i32) {
+    let Some(profile) = chat_profile(chat_id.0, *profile_id).await.unwrap() else {
+        bot.send_message(chat_id, "Профиль не найден. Возможно, он уже удалён.").await.unwrap();
+        return;
+    };
+
+    let confirm_key = button(
+        "Да, удалить", 
+        CallbackAction::ProfileDeleteOk { profile_id: profile.id }
+    );
+    let away_key = button("Назад", CallbackAction::Profiles { page: 0 });
+    let markup = InlineKeyboardMarkup::new([[confirm_key], [away_key]]);
+
+    let text = format!(
+        "Удалить профиль «{}»? Вместе с ним удалятся его правила отслеживания и напоминания. Записи в ЕМИАС останутся.", 
+        display_name(&profile)
+    );
+    bot.edit_message_text(chat_id, message_id, text).reply_markup(markup).await.unwrap();
+}
+
+pub async fn remove_profile_ok(bot: Bot, chat_id:ChatId, message_id:MessageId, profile_id: &i32) {
+    match remove_profile(chat_id.0, *profile_id).await {
+        Ok(_) => get_profiles(bot, chat_id, message_id, 0).await,
+        Err(_) => {
+            bot.send_message(chat_id, "Не удалось удалить профиль. Попробуйте позже.").await.unwrap();
+        }
+    }
+}
+
+pub async fn verify_profile_again(bot: Bot, chat_id:ChatId, profile_id: &i32) {
+    match chat_profile(chat_id.0, *profile_id).await.unwrap() {
+        Some(profile) => {
+            let mut held: info::ActiveModel = profile.into();
+            hold_for_verification(&mut held);
+            let profile = held.update(DB.get().unwrap()).await.unwrap();
+            verify_and_report(&bot, &profile).await;
+        },
+        None => {
+            bot.send_message(chat_id, "Профиль не найден. Возможно, он уже удалён.").await.unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{block_on, bot, emias_calls, insert_profile, sent_texts};
+
+    const TARGET: ScheduleTarget = ScheduleTarget { referral_id: 172704541983, resource_id: 19605506587, complex_resource_id: 200992738 };
+
+    fn slot(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("2024-09-20 {}", time), "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn repeated_confirmation_books_once() {
+        block_on(async {
+            let user = insert_profile(201).await;
+            let before = emias_calls("createAppointment");
+
+            for _ in 0..2 {
+                book_slot(bot(), user.clone(), ChatId(201), MessageId(1), &TARGET, &slot("08:00")).await;
+            }
+
+            assert_eq!(emias_calls("createAppointment") - before, 1);
+            assert_eq!(sent_texts(201).iter().filter(|text| text.starts_with("Вы записаны!")).count(), 1);
+        });
+    }
+
+    #[test]
+    fn service_error_is_not_reported_as_taken_slot() {
+        block_on(async {
+            let user = insert_profile(202).await;
+
+            book_slot(bot(), user, ChatId(202), MessageId(1), &TARGET, &slot("08:12")).await;
+
+            let texts = sent_texts(202);
+            assert!(texts.iter().any(|text| text.starts_with("Не удалось записаться.") && text.contains("Internal error")));
+            assert!(!texts.iter().any(|text| text.contains("уже занято")));
+        });
+    }
+}
@@ -0,0 +1,479 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardButtonKind};
+
+use crate::em_commands::callback_session;
+use crate::helper::{ScheduleTarget, DAY_FORMAT, SLOT_FORMAT};
+
+/// Версия формата данных кнопок. Кнопки с другой версией (или без неё) считаются устаревшими:
+/// при изменении формата достаточно поднять версию, и старые сообщения не сломают обработчик.
+pub const CALLBACK_VERSION: &str = "v2";
+
+/// Лимит Telegram на `callback_data` в байтах.
+pub const CALLBACK_DATA_LIMIT: usize = 64;
+
+/// Действие inline-кнопки. Данные кнопки: `v2[/@<profile_id>]/<маршрут>[/<аргументы>]`
+/// или `v2/~<токен>`, если они длиннее лимита (см. [`callback_session`]). `page` — страница
+/// списка (с нуля), см. [`super::pagination`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallbackAction {
+    /// Кнопка-подпись («Нет свободного времени»), ничего не делает.
+    Noop,
+    MainMenu,
+    Referrals { page: usize },
+    RefreshReferrals,
+    Doctors { referral_id: u64, page: usize },
+    RefreshDoctors { referral_id: u64 },
+    Schedule { target: ScheduleTarget, page: usize },
+    Slots { target: ScheduleTarget, date: NaiveDate, page: usize },
+    ConfirmSlot { target: ScheduleTarget, start: NaiveDateTime },
+    BookSlot { target: ScheduleTarget, start: NaiveDateTime },
+    Appointments { page: usize },
+    CancelAppointment { appointment_id: u64 },
+    CancelAppointmentOk { appointment_id: u64 },
+    ShiftDays { appointment_id: u64, page: usize },
+    ShiftSlots { appointment_id: u64, date: NaiveDate, page: usize },
+    ShiftConfirm { appointment_id: u64, start: NaiveDateTime },
+    ShiftOk { appointment_id: u64, start: NaiveDateTime },
+    WatchResource { referral_id: u64, resource_id: u64 },
+    WatchRules { page: usize },
+    Unwatch { rule_id: i32 },
+    Profiles { page: usize },
+    ProfileUse { profile_id: i32 },
+    ProfileDelete { profile_id: i32 },
+    ProfileDeleteOk { profile_id: i32 },
+    ProfileVerify { profile_id: i32 },
+    OnboardingCancel,
+    OnboardingRestart,
+    OnboardingSave,
+}
+
+/// Данные кнопки: действие и профиль, от имени которого оно выполняется. Кнопки уведомлений
+/// помечены профилем — нажатие делает его активным.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallbackData {
+    pub profile_id: Option<i32>,
+    pub action: CallbackAction,
+}
+
+/// Почему данные кнопки не удалось собрать или разобрать.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallbackError {
+    /// Закодированные данные длиннее [`CALLBACK_DATA_LIMIT`].
+    TooLong(String),
+    /// Кнопка из сообщения со старым форматом данных.
+    Outdated(String),
+    /// Неизвестный маршрут.
+    Unknown(String),
+    /// Маршрут известен, но аргументов не хватает или они не разбираются.
+    Malformed(String),
+    /// Токен сессии истёк или неизвестен (например, после перезапуска бота).
+    Expired(String),
+}
+
+impl CallbackError {
+    /// Пояснение для пользователя бота (всплывающее окно у кнопки).
+    pub fn user_message(&self) -> &'static str {
+        match self {
+            CallbackError::TooLong(_) | CallbackError::Malformed(_) => "Эта кнопка не работает. Откройте меню заново.",
+            CallbackError::Outdated(_) => "Эта кнопка устарела. Откройте меню заново — например, командой /start.",
+            CallbackError::Unknown(_) => "Неизвестная кнопка. Возможно, меню устарело — откройте его заново.",
+            CallbackError::Expired(_) => "Это меню устарело. Обновите его — откройте список заново."
+        }
+    }
+}
+
+impl fmt::Display for CallbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallbackError::TooLong(data) => write!(f, "callback data exceeds {} bytes: {}", CALLBACK_DATA_LIMIT, data),
+            CallbackError::Outdated(data) => write!(f, "outdated callback data: {}", data),
+            CallbackError::Unknown(data) => write!(f, "unknown callback route: {}", data),
+            CallbackError::Malformed(data) => write!(f, "malformed callback data: {}", data),
+            CallbackError::Expired(data) => write!(f, "expired callback session: {}", data)
+        }
+    }
+}
+
+impl std::error::Error for CallbackError {}
+
+impl CallbackAction {
+    /// Нужны ли действию полис и дата рождения профиля, то есть обращается ли оно к ЕМИАС.
+    pub fn needs_patient(&self) -> bool {
+        !matches!(
+            self,
+            CallbackAction::Noop
+            | CallbackAction::MainMenu
+            | CallbackAction::WatchRules { .. }
+            | CallbackAction::Unwatch { .. }
+            | CallbackAction::Profiles { .. }
+            | CallbackAction::ProfileUse { .. }
+            | CallbackAction::ProfileDelete { .. }
+            | CallbackAction::ProfileDeleteOk { .. }
+            | CallbackAction::ProfileVerify { .. }
+            | CallbackAction::OnboardingCancel
+            | CallbackAction::OnboardingRestart
+            | CallbackAction::OnboardingSave
+        )
+    }
+
+    fn route(&self) -> &'static str {
+        match self {
+            CallbackAction::Noop => "_",
+            CallbackAction::MainMenu => "back_to_main",
+            CallbackAction::Referrals { .. } => "get_referrals",
+            CallbackAction::RefreshReferrals => "refresh_referrals",
+            CallbackAction::Doctors { .. } => "get_doctors",
+            CallbackAction::RefreshDoctors { .. } => "refresh_doctors",
+            CallbackAction::Schedule { .. } => "get_shedule",
+            CallbackAction::Slots { .. } => "get_slots",
+            CallbackAction::ConfirmSlot { .. } => "confirm_slot",
+            CallbackAction::BookSlot { .. } => "book_slot",
+            CallbackAction::Appointments { .. } => "get_appointments",
+            CallbackAction::CancelAppointment { .. } => "cancel_app",
+            CallbackAction::CancelAppointmentOk { .. } => "cancel_ok",
+            CallbackAction::ShiftDays { .. } => "shift_days",
+            CallbackAction::ShiftSlots { .. } => "shift_slots",
+            CallbackAction::ShiftConfirm { .. } => "shift_confirm",
+            CallbackAction::ShiftOk { .. } => "shift_ok",
+            CallbackAction::WatchResource { .. } => "watch_res",
+            CallbackAction::WatchRules { .. } => "watch_rules",
+            CallbackAction::Unwatch { .. } => "unwatch",
+            CallbackAction::Profiles { .. } => "profiles",
+            CallbackAction::ProfileUse { .. } => "profile_use",
+            CallbackAction::ProfileDelete { .. } => "profile_del",
+            CallbackAction::ProfileDeleteOk { .. } => "profile_del_ok",
+            CallbackAction::ProfileVerify { .. } => "profile_verify",
+            CallbackAction::OnboardingCancel => "onb_cancel",
+            CallbackAction::OnboardingRestart => "onb_restart",
+            CallbackAction::OnboardingSave => "onb_save"
+        }
+    }
+
+    fn args(&self) -> Vec<String> {
+        match self {
+            CallbackAction::Noop
+            | CallbackAction::MainMenu
+            | CallbackAction::RefreshReferrals
+            | CallbackAction::OnboardingCancel
+            | CallbackAction::OnboardingRestart
+            | CallbackAction::OnboardingSave => vec![],
+            CallbackAction::Referrals { page }
+            | CallbackAction::Appointments { page }
+            | CallbackAction::WatchRules { page }
+            | CallbackAction::Profiles { page } => vec![page.to_string()],
+            CallbackAction::Doctors { referral_id, page } => vec![referral_id.to_string(), page.to_string()],
+            CallbackAction::RefreshDoctors { referral_id } => vec![referral_id.to_string()],
+            CallbackAction::Schedule { target, page } => vec![target.path(), page.to_string()],
+            CallbackAction::Slots { target, date, page } => vec![target.path(), date.format(DAY_FORMAT).to_string(), page.to_string()],
+            CallbackAction::ConfirmSlot { target, start } | CallbackAction::BookSlot { target, start } => {
+                vec![target.path(), start.format(SLOT_FORMAT).to_string()]
+            },
+            CallbackAction::CancelAppointment { appointment_id }
+            | CallbackAction::CancelAppointmentOk { appointment_id } => vec![appointment_id.to_string()],
+            CallbackAction::ShiftDays { appointment_id, page } => vec![appointment_id.to_string(), page.to_string()],
+            CallbackAction::ShiftSlots { appointment_id, date, page } => {
+                vec![appointment_id.to_string(), date.format(DAY_FORMAT).to_string(), page.to_string()]
+            },
+            CallbackAction::ShiftConfirm { appointment_id, start } | CallbackAction::ShiftOk { appointment_id, start } => {
+                vec![appointment_id.to_string(), start.format(SLOT_FORMAT).to_string()]
+            },
+            CallbackAction::WatchResource { referral_id, resource_id } => vec![referral_id.to_string(), resource_id.to_string()],
+            CallbackAction::Unwatch { rule_id } => vec![rule_id.to_string()],
+            CallbackAction::ProfileUse { profile_id }
+            | CallbackAction::ProfileDelete { profile_id }
+            | CallbackAction::ProfileDeleteOk { profile_id }
+            | CallbackAction::ProfileVerify { profile_id } => vec![profile_id.to_string()]
+        }
+    }
+
+    fn parse(route: &str, args: &[&str], data: &str) -> Result<Self, CallbackError> {
+        let args = Args { args, data };
+
+        let action = match route {
+            "_" => CallbackAction::Noop,
+            "back_to_main" => CallbackAction::MainMenu,
+            "get_referrals" => CallbackAction::Referrals { page: args.get(0)? },
+            "refresh_referrals" => CallbackAction::RefreshReferrals,
+            "get_doctors" => CallbackAction::Doctors { referral_id: args.get(0)?, page: args.get(1)? },
+            "refresh_doctors" => CallbackAction::RefreshDoctors { referral_id: args.get(0)? },
+            "get_shedule" => CallbackAction::Schedule { target: args.target()?, page: args.get(3)? },
+            "get_slots" => CallbackAction::Slots { target: args.target()?, date: args.date(3)?, page: args.get(4)? },
+            "confirm_slot" => CallbackAction::ConfirmSlot { target: args.target()?, start: args.start(3)? },
+            "book_slot" => CallbackAction::BookSlot { target: args.target()?, start: args.start(3)? },
+            "get_appointments" => CallbackAction::Appointments { page: args.get(0)? },
+            "cancel_app" => CallbackAction::CancelAppointment { appointment_id: args.get(0)? },
+            "cancel_ok" => CallbackAction::CancelAppointmentOk { appointment_id: args.get(0)? },
+            "shift_days" => CallbackAction::ShiftDays { appointment_id: args.get(0)?, page: args.get(1)? },
+            "shift_slots" => CallbackAction::ShiftSlots { appointment_id: args.get(0)?, date: args.date(1)?, page: args.get(2)? },
+            "shift_confirm" => CallbackAction::ShiftConfirm { appointment_id: args.get(0)?, start: args.start(1)? },
+            "shift_ok" => CallbackAction::ShiftOk { appointment_id: args.get(0)?, start: args.start(1)? },
+            "watch_res" => CallbackAction::WatchResource { referral_id: args.get(0)?, resource_id: args.get(1)? },
+            "watch_rules" => CallbackAction::WatchRules { page: args.get(0)? },
+            "unwatch" => CallbackAction::Unwatch { rule_id: args.get(0)? },
+            "profiles" => CallbackAction::Profiles { page: args.get(0)? },
+            "profile_use" => CallbackAction::ProfileUse { profile_id: args.get(0)? },
+            "profile_del" => CallbackAction::ProfileDelete { profile_id: args.get(0)? },
+            "profile_del_ok" => CallbackAction::ProfileDeleteOk { profile_id: args.get(0)? },
+            "profile_verify" => CallbackAction::ProfileVerify { profile_id: args.get(0)? },
+            "onb_cancel" => CallbackAction::OnboardingCancel,
+            "onb_restart" => CallbackAction::OnboardingRestart,
+            "onb_save" => CallbackAction::OnboardingSave,
+            _ => return Err(CallbackError::Unknown(data.to_string()))
+        };
+
+        // Лишние аргументы — тоже признак чужого или повреждённого формата.
+        if action.args().iter().map(|arg| arg.split('/').count()).sum::<usize>() != args.args.len() {
+            return Err(CallbackError::Malformed(data.to_string()));
+        }
+
+        Ok(action)
+    }
+
+    /// Кнопки этапа знакомства с ботом обрабатываются диалогом, а не общим обработчиком.
+    pub fn is_onboarding(&self) -> bool {
+        matches!(self, CallbackAction::OnboardingCancel | CallbackAction::OnboardingRestart | CallbackAction::OnboardingSave)
+    }
+}
+
+impl ScheduleTarget {
+    /// Часть данных inline-кнопки вида `<referral_id>/<resource_id>/<complex_resource_id>`.
+    fn path(&self) -> String {
+        format!("{}/{}/{}", self.referral_id, self.resource_id, self.complex_resource_id)
+    }
+
+    fn from_parts(parts: &[&str]) -> Option<Self> {
+        Some(Self {
+            referral_id: parts.first()?.parse().ok()?,
+            resource_id: parts.get(1)?.parse().ok()?,
+            complex_resource_id: parts.get(2)?.parse().ok()?
+        })
+    }
+}
+
+/// Аргументы маршрута с ошибкой разбора, в которой видны исходные данные кнопки.
+struct Args<'a> {
+    args: &'a [&'a str],
+    data: &'a str,
+}
+
+impl Args<'_> {
+    fn get<T: FromStr>(&self, index: usize) -> Result<T, CallbackError> {
+        self.args.get(index)
+            .and_then(|arg| arg.parse().ok())
+            .ok_or_else(|| CallbackError::Malformed(self.data.to_string()))
+    }
+
+    fn target(&self) -> Result<ScheduleTarget, CallbackError> {
+        ScheduleTarget::from_parts(self.args).ok_or_else(|| CallbackError::Malformed(self.data.to_string()))
+    }
+
+    fn date(&self, index: usize) -> Result<NaiveDate, CallbackError> {
+        self.args.get(index)
+            .and_then(|arg| NaiveDate::parse_from_str(arg, DAY_FORMAT).ok())
+            .ok_or_else(|| CallbackError::Malformed(self.data.to_string()))
+    }
+
+    fn start(&self, index: usize) -> Result<NaiveDateTime, CallbackError> {
+        self.args.get(index)
+            .and_then(|arg| NaiveDateTime::parse_from_str(arg, SLOT_FORMAT).ok())
+            .ok_or_else(|| CallbackError::Malformed(self.data.to_string()))
+    }
+}
+
+impl CallbackData {
+    pub fn new(action: CallbackAction) -> Self {
+        Self { profile_id: None, action }
+    }
+
+    pub fn for_profile(profile_id: i32, action: CallbackAction) -> Self {
+        Self { profile_id: Some(profile_id), action }
+    }
+
+    pub fn encode(&self) -> Result<String, CallbackError> {
+        let data = self.to_data();
+        if data.len() > CALLBACK_DATA_LIMIT {
+            return Err(CallbackError::TooLong(data));
+        }
+
+        Ok(data)
+    }
+
+    pub fn decode(data: &str) -> Result<Self, CallbackError> {
+        if data.len() > CALLBACK_DATA_LIMIT {
+            return Err(CallbackError::TooLong(data.to_string()));
+        }
+
+        Self::from_data(data)
+    }
+
+    /// Данные кнопки без проверки лимита — для хранилища сессий.
+    pub fn to_data(&self) -> String {
+        let mut parts = vec![CALLBACK_VERSION.to_string()];
+        if let Some(profile_id) = self.profile_id {
+            parts.push(format!("@{}", profile_id));
+        }
+        parts.push(self.action.route().to_string());
+        parts.extend(self.action.args());
+
+        parts.join("/")
+    }
+
+    /// Разбирает данные кнопки без проверки лимита — для хранилища сессий.
+    pub fn from_data(data: &str) -> Result<Self, CallbackError> {
+        let mut parts = data.split('/').collect::<Vec<&str>>();
+        if parts.first() != Some(&CALLBACK_VERSION) {
+            return Err(CallbackError::Outdated(data.to_string()));
+        }
+        parts.remove(0);
+
+        let profile_id = match parts.first().and_then(|part| part.strip_prefix('@')) {
+            Some(id) => {
+                let id = id.parse().map_err(|_| CallbackError::Malformed(data.to_string()))?;
+                parts.remove(0);
+                Some(id)
+            },
+            None => None
+        };
+
+        let Some((route, args)) = parts.split_first() else {
+            return Err(CallbackError::Malformed(data.to_string()));
+        };
+
+        Ok(Self { profile_id, action: CallbackAction::parse(route, args, data)? })
+    }
+
+    /// Inline-кнопка с этими данными. Данные, которые не помещаются в лимит Telegram,
+    /// остаются на сервере, а кнопка несёт короткий токен сессии.
+    pub fn button(&self, text: impl Into<String>) -> InlineKeyboardButton {
+        let data = match self.encode() {
+            Ok(data) => data,
+            Err(_) => callback_session::store(self.clone())
+        };
+
+        InlineKeyboardButton::new(text, InlineKeyboardButtonKind::CallbackData(data))
+    }
+}
+
+/// Кнопка действия от имени активного профиля.
+pub fn button(text: impl Into<String>, action: CallbackAction) -> InlineKeyboardButton {
+    CallbackData::new(action).button(text)
+}
+
+/// Кнопка уведомления: действие от имени профиля `profile_id`.
+pub fn profile_button(text: impl Into<String>, profile_id: i32, action: CallbackAction) -> InlineKeyboardButton {
+    CallbackData::for_profile(profile_id, action).button(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: ScheduleTarget = ScheduleTarget { referral_id: 172704541983, resource_id: 19605506587, complex_resource_id: 200992738 };
+
+    fn all_actions() -> Vec<CallbackAction> {
+        let date = NaiveDate::from_ymd_opt(2024, 9, 20).unwrap();
+        let start = date.and_hms_opt(8, 12, 0).unwrap();
+
+        vec![
+            CallbackAction::Noop,
+            CallbackAction::MainMenu,
+            CallbackAction::Referrals { page: 2 },
+            CallbackAction::RefreshReferrals,
+            CallbackAction::Doctors { referral_id: 172704541983, page: 1 },
+            CallbackAction::RefreshDoctors { referral_id: 172704541983 },
+            CallbackAction::Schedule { target: TARGET, page: 3 },
+            CallbackAction::Slots { target: TARGET, date, page: 1 },
+            CallbackAction::ConfirmSlot { target: TARGET, start },
+            CallbackAction::BookSlot { target: TARGET, start },
+            CallbackAction::Appointments { page: 0 },
+            CallbackAction::CancelAppointment { appointment_id: 4100001 },
+            CallbackAction::CancelAppointmentOk { appointment_id: 4100001 },
+            CallbackAction::ShiftDays { appointment_id: 4100001, page: 1 },
+            CallbackAction::ShiftSlots { appointment_id: 4100001, date, page: 2 },
+            CallbackAction::ShiftConfirm { appointment_id: 4100001, start },
+            CallbackAction::ShiftOk { appointment_id: 4100001, start },
+            CallbackAction::WatchResource { referral_id: 172704541983, resource_id: 19605506587 },
+            CallbackAction::WatchRules { page: 1 },
+            CallbackAction::Unwatch { rule_id: 12 },
+            CallbackAction::Profiles { page: 0 },
+            CallbackAction::ProfileUse { profile_id: 5 },
+            CallbackAction::ProfileDelete { profile_id: 5 },
+            CallbackAction::ProfileDeleteOk { profile_id: 5 },
+            CallbackAction::ProfileVerify { profile_id: 5 },
+            CallbackAction::OnboardingCancel,
+            CallbackAction::OnboardingRestart,
+            CallbackAction::OnboardingSave,
+        ]
+    }
+
+    #[test]
+    fn every_action_round_trips() {
+        for action in all_actions() {
+            for data in [CallbackData::new(action.clone()), CallbackData::for_profile(42, action.clone())] {
+                let encoded = data.to_data();
+                assert!(encoded.starts_with(&format!("{}/", CALLBACK_VERSION)), "{}", encoded);
+                assert_eq!(CallbackData::from_data(&encoded), Ok(data.clone()), "{}", encoded);
+
+                if let Ok(encoded) = data.encode() {
+                    assert_eq!(CallbackData::decode(&encoded), Ok(data));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn routes_are_unique() {
+        let actions = all_actions();
+        let routes = actions.iter().map(|action| action.route()).collect::<std::collections::HashSet<&str>>();
+        assert_eq!(routes.len(), actions.len());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        assert_eq!(CallbackData::decode("v1/get_referrals/0"), Err(CallbackError::Outdated("v1/get_referrals/0".to_string())));
+        assert!(matches!(CallbackData::decode("get_referrals/0"), Err(CallbackError::Outdated(_))));
+        assert!(matches!(CallbackData::decode(""), Err(CallbackError::Outdated(_))));
+    }
+
+    #[test]
+    fn rejects_wrong_arguments() {
+        assert!(matches!(CallbackData::decode("v2/get_referrals/0/1"), Err(CallbackError::Malformed(_))));
+        assert!(matches!(CallbackData::decode("v2/back_to_main/1"), Err(CallbackError::Malformed(_))));
+        assert!(matches!(CallbackData::decode("v2/get_doctors/1"), Err(CallbackError::Malformed(_))));
+        assert!(matches!(CallbackData::decode("v2/get_referrals/x"), Err(CallbackError::Malformed(_))));
+        assert!(matches!(CallbackData::decode("v2/get_slots/1/2/3/2024-09-20/0"), Err(CallbackError::Malformed(_))));
+        assert!(matches!(CallbackData::decode("v2/@x/get_referrals/0"), Err(CallbackError::Malformed(_))));
+        assert!(matches!(CallbackData::decode("v2/@1"), Err(CallbackError::Malformed(_))));
+    }
+
+    #[test]
+    fn rejects_unknown_route() {
+        assert_eq!(CallbackData::decode("v2/get_everything"), Err(CallbackError::Unknown("v2/get_everything".to_string())));
+    }
+
+    #[test]
+    fn rejects_oversized_data() {
+        let data = format!("v2/get_referrals/{}", "0".repeat(CALLBACK_DATA_LIMIT));
+        assert!(matches!(CallbackData::decode(&data), Err(CallbackError::TooLong(_))));
+
+        let target = ScheduleTarget { referral_id: u64::MAX, resource_id: u64::MAX, complex_resource_id: u64::MAX };
+        let start = NaiveDate::from_ymd_opt(2024, 9, 20).unwrap().and_hms_opt(8, 0, 0).unwrap();
+        let long = CallbackData::for_profile(i32::MAX, CallbackAction::BookSlot { target, start });
+        assert!(matches!(long.encode(), Err(CallbackError::TooLong(_))));
+        // Без проверки лимита такие данные разбираются — так их хранит `callback_session`.
+        assert_eq!(CallbackData::from_data(&long.to_data()), Ok(long));
+    }
+
+    #[test]
+    fn only_emias_screens_need_patient() {
+        assert!(CallbackAction::RefreshReferrals.needs_patient());
+        assert!(CallbackAction::RefreshDoctors { referral_id: 1 }.needs_patient());
+        assert!(CallbackAction::Appointments { page: 0 }.needs_patient());
+
+        assert!(!CallbackAction::MainMenu.needs_patient());
+        assert!(!CallbackAction::Profiles { page: 0 }.needs_patient());
+        assert!(!CallbackAction::OnboardingSave.needs_patient());
+    }
+}
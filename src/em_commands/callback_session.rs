@@ -0,0 +1,209 @@
+use std::{
+    collections::HashMap,
+    env,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rand::{distributions::Alphanumeric, Rng};
+use sea_orm::{prelude::*, ActiveValue};
+
+use crate::em_commands::callback_data::{CallbackData, CallbackError, CALLBACK_VERSION};
+use crate::entities::{callback_session, prelude::*};
+use crate::DB;
+
+/// Сколько живёт токен кнопки, если не задан `CALLBACK_SESSION_TTL` (в секундах).
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Длина токена: вместе с префиксом `<CALLBACK_VERSION>/~` (сейчас `v2/~`) он занимает
+/// малую часть 64 байт `callback_data`.
+const TOKEN_LEN: usize = 12;
+
+/// Истёкшие токены удаляются не при каждом сохранении, а раз в столько сохранений.
+const SWEEP_EVERY: usize = 256;
+
+lazy_static::lazy_static! {
+    static ref SESSIONS: SessionStore = SessionStore::from_env();
+}
+
+/// Хранилище данных кнопок, которые не помещаются в `callback_data`: кнопка несёт токен,
+/// а полный контекст навигации лежит на сервере до истечения срока. Токены хранятся в
+/// памяти; с `CALLBACK_SESSION_DB=1` они дублируются в таблицу `callback_session` и
+/// переживают перезапуск бота.
+struct SessionStore {
+    ttl: Duration,
+    persist: bool,
+    sessions: Mutex<Sessions>,
+}
+
+#[derive(Default)]
+struct Sessions {
+    entries: HashMap<String, (CallbackData, Instant)>,
+    inserts: usize,
+}
+
+impl SessionStore {
+    fn new(ttl: Duration, persist: bool) -> Self {
+        Self { ttl, persist, sessions: Mutex::new(Sessions::default()) }
+    }
+
+    fn from_env() -> Self {
+        Self::new(
+            env::var("CALLBACK_SESSION_TTL").ok().and_then(|v| v.parse().ok()).map_or(DEFAULT_SESSION_TTL, Duration::from_secs),
+            env::var("CALLBACK_SESSION_DB").is_ok_and(|v| matches!(v.as_str(), "1" | "true" | "yes"))
+        )
+    }
+
+    /// Сохраняет данные под новым токеном. Второе значение — пора ли удалить истёкшие
+    /// токены (в памяти это уже сделано).
+    fn insert(&self, data: CallbackData, now: Instant) -> (String, bool) {
+        let token = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(TOKEN_LEN)
+            .map(char::from)
+            .collect::<String>();
+
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.inserts = sessions.inserts.wrapping_add(1);
+        let sweep = sessions.inserts.is_multiple_of(SWEEP_EVERY);
+        if sweep {
+            sessions.entries.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+        sessions.entries.insert(token.clone(), (data, now + self.ttl));
+
+        (token, sweep)
+    }
+
+    fn get(&self, token: &str, now: Instant) -> Option<CallbackData> {
+        self.sessions.lock().unwrap()
+            .entries
+            .get(token)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(data, _)| data.clone())
+    }
+}
+
+/// Сохраняет данные кнопки и возвращает `callback_data` с токеном.
+pub fn store(data: CallbackData) -> String {
+    let (token, sweep) = SESSIONS.insert(data.clone(), Instant::now());
+
+    // Клавиатуры собираются синхронно, поэтому в базу токен пишется в фоне. До записи он
+    // всё равно найдётся в памяти.
+    if SESSIONS.persist {
+        let token = token.clone();
+        tokio::spawn(async move {
+            if let Err(err) = persist(token, data, sweep).await {
+                log::error!("Failed to persist callback session: {}", err);
+            }
+        });
+    }
+
+    format!("{}/~{}", CALLBACK_VERSION, token)
+}
+
+async fn persist(token: String, data: CallbackData, sweep: bool) -> Result<(), DbErr> {
+    let now = chrono::Utc::now().naive_utc();
+    let ttl = chrono::Duration::from_std(SESSIONS.ttl).unwrap_or(chrono::Duration::hours(1));
+
+    if sweep {
+        CallbackSession::delete_many()
+            .filter(callback_session::Column::ExpiresAt.lt(now))
+            .exec(DB.get().unwrap())
+            .await?;
+    }
+
+    callback_session::ActiveModel {
+        token: ActiveValue::Set(token),
+        data: ActiveValue::Set(data.to_data()),
+        expires_at: ActiveValue::Set(now + ttl),
+    }.insert(DB.get().unwrap()).await?;
+
+    Ok(())
+}
+
+/// Разбирает `callback_data` нажатой кнопки; токены ищутся в хранилище сессий.
+pub async fn resolve(data: &str) -> Result<CallbackData, CallbackError> {
+    let Some(token) = data.strip_prefix(CALLBACK_VERSION).and_then(|rest| rest.strip_prefix("/~")) else {
+        return CallbackData::decode(data);
+    };
+
+    if let Some(found) = SESSIONS.get(token, Instant::now()) {
+        return Ok(found);
+    }
+
+    if SESSIONS.persist {
+        let row = CallbackSession::find_by_id(token.to_string())
+            .filter(callback_session::Column::ExpiresAt.gt(chrono::Utc::now().naive_utc()))
+            .one(DB.get().unwrap())
+            .await;
+
+        match row {
+            Ok(Some(row)) => return CallbackData::from_data(&row.data),
+            Ok(None) => {},
+            Err(err) => log::error!("Failed to load callback session: {}", err)
+        }
+    }
+
+    Err(CallbackError::Expired(data.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::em_commands::callback_data::CallbackAction;
+    use crate::test_support::block_on;
+
+    fn data(page: usize) -> CallbackData {
+        CallbackData::for_profile(7, CallbackAction::Referrals { page })
+    }
+
+    #[test]
+    fn token_resolves_to_stored_data() {
+        block_on(async {
+            let callback_data = store(data(3));
+
+            assert!(callback_data.starts_with(&format!("{}/~", CALLBACK_VERSION)));
+            assert_eq!(callback_data.len(), CALLBACK_VERSION.len() + 2 + TOKEN_LEN);
+            assert_eq!(resolve(&callback_data).await, Ok(data(3)));
+        });
+    }
+
+    #[test]
+    fn unknown_token_is_expired() {
+        block_on(async {
+            let unknown = format!("{}/~{}", CALLBACK_VERSION, "x".repeat(TOKEN_LEN));
+            assert_eq!(resolve(&unknown).await, Err(CallbackError::Expired(unknown.clone())));
+
+            // Данные без токена разбираются как обычно.
+            assert_eq!(resolve("v2/@7/get_referrals/3").await, Ok(data(3)));
+        });
+    }
+
+    #[test]
+    fn token_expires_after_ttl() {
+        let store = SessionStore::new(Duration::from_secs(60), false);
+        let now = Instant::now();
+
+        let (token, _) = store.insert(data(1), now);
+
+        assert_eq!(store.get(&token, now + Duration::from_secs(59)), Some(data(1)));
+        assert_eq!(store.get(&token, now + Duration::from_secs(61)), None);
+    }
+
+    #[test]
+    fn expired_tokens_are_swept_periodically() {
+        let store = SessionStore::new(Duration::from_secs(60), false);
+        let now = Instant::now();
+        let later = now + Duration::from_secs(120);
+
+        let (expired, sweep) = store.insert(data(1), now);
+        assert!(!sweep);
+
+        let sweeps = (1..SWEEP_EVERY).filter(|page| store.insert(data(*page), later).1).count();
+        assert_eq!(sweeps, 1);
+
+        let sessions = store.sessions.lock().unwrap();
+        assert!(!sessions.entries.contains_key(&expired));
+        assert_eq!(sessions.entries.len(), SWEEP_EVERY - 1);
+    }
+}
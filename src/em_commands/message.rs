@@ -1,105 +1,263 @@
-use crate::{EmCommand, DB};
+use crate::{
+    em_commands::{
+        callback::{appointments_markup, watch_rules_markup}, 
+        onboarding::{self, parse_birth_date, OnboardingDialogue}
+    }, 
+    helper::collect_appointments_data, 
+    oms, 
+    poller::{hold_for_verification, reset_failures}, 
+    profiles::{active_profile, add_profile, chat_profiles, collect_profiles_data, display_name, profiles_markup}, 
+    verification::verify_and_report, 
+    watch::{collect_watch_rules_data, parse_watch_rule, WATCH_HELP}, 
+    EmCommand, DB, EMIAS
+};
 use sea_orm::{prelude::*, ActiveValue};
 use teloxide::{prelude::*, utils::command::BotCommands};
 
-use crate::entities::{info, prelude::*};
-
-use chrono::NaiveDate;
+use crate::entities::{info, prelude::*, watch_rule};
 
 
 pub async fn help(bot: Bot, msg: Message) {
     bot.send_message(msg.chat.id, EmCommand::descriptions().to_string()).await.unwrap();
 }
 
-pub async fn start(bot: Bot, msg: Message) {
-    let q = Info::find().filter(info::Column::ChatId.eq(msg.chat.id.0)).one(DB.get().unwrap()).await.unwrap();
+pub async fn start(bot: Bot, msg: Message, dialogue: OnboardingDialogue) {
+    let q = active_profile(msg.chat.id.0).await.unwrap();
     
-    match q {
-        Some(_) => { bot.send_message(msg.chat.id, "Пользователь с вашими данными найден. Обновление базы не требуется.").await.unwrap(); },
+    let profile = match q {
+        Some(profile) if profile.oms_card.is_some() && profile.date_birth.is_some() => {
+            bot.send_message(
+                msg.chat.id, 
+                format!(
+                    "Профиль «{}» уже заполнен. Изменить данные можно командами `/omscard` и `/datebirth`, добавить ещё один профиль — `/addprofile Имя`.", 
+                    display_name(&profile)
+                )
+            ).await.unwrap();
+            return;
+        },
+        Some(profile) => profile,
         None => {
-            println!("user:{} \nchat:{}", &msg.chat.id.0, &msg.from.clone().unwrap().id.0);
-
-            let res = Info::insert(info::ActiveModel{
+            let res = info::ActiveModel{
                 chat_id: ActiveValue::Set(msg.chat.id.0),
                 ..Default::default()
-            }).exec(DB.get().unwrap()).await;
+            }.insert(DB.get().unwrap()).await;
 
             match res {
-                Ok(_) => { bot.send_message(msg.chat.id, "Пользователь с вашими данными не найден. Инициализирована новая запись. Используйте команду `/help` для получения справки.").await.unwrap(); },
-                Err(_) =>{ bot.send_message(msg.chat.id, "Не удалось инициализировать запись. Попробуйте позже или обратитесь к автору этого ужаса за помощью.").await.unwrap(); }
+                Ok(profile) => profile,
+                Err(_) => {
+                    bot.send_message(msg.chat.id, "Не удалось инициализировать запись. Попробуйте позже или обратитесь к автору этого ужаса за помощью.").await.unwrap();
+                    return;
+                }
             }
         }
-    }
+    };
+
+    onboarding::begin(&bot, &dialogue, &profile).await.unwrap();
 }
 
 pub async fn oms_card(bot: Bot, msg: Message, oms:String) {
-    if oms.len() != 16 || oms.parse::<i64>().is_err() {
-        bot.send_message(msg.chat.id, format!("Полис должен быть указан в формате 16 чисел без дополнительных символов и пробелов.")).await.unwrap();
-    }
-    let q = Info::find().filter(info::Column::ChatId.eq(msg.chat.id.0)).one(DB.get().unwrap()).await.unwrap();
+    let oms = match oms::parse(&oms) {
+        Ok(oms) => oms,
+        Err(err) => {
+            bot.send_message(msg.chat.id, format!("{} \nНапример: `/omscard 7788899730000765`.", err)).await.unwrap();
+            return;
+        }
+    };
+    let q = active_profile(msg.chat.id.0).await.unwrap();
     match q {
         Some(v) => {
             let mut nv: info::ActiveModel = v.into();
-            nv.oms_card = ActiveValue::Set(Some(oms.parse::<i64>().unwrap()));
+            nv.oms_card = ActiveValue::Set(Some(oms));
+            nv.verified = ActiveValue::Set(false);
+            reset_failures(&mut nv);
+            hold_for_verification(&mut nv);
             let updated = nv.update(DB.get().unwrap()).await;
             match updated {
-                Ok(_) => { 
-                    bot.send_message(msg.chat.id, format!("Ваш новый полис ОМС {oms}.")).await.unwrap(); 
+                Ok(profile) => { 
+                    bot.send_message(msg.chat.id, format!("Ваш новый полис ОМС {}.", oms::format(oms))).await.unwrap(); 
+                    verify_and_report(&bot, &profile).await;
                 },
                 Err(_) => { 
-                    bot.send_message(msg.chat.id, format!("Не удалось обновить ваш полис. Попробуйте позже или обратитесь к автору этого безобразия.")).await.unwrap(); 
+                    bot.send_message(msg.chat.id, "Не удалось обновить ваш полис. Попробуйте позже или обратитесь к автору этого безобразия.").await.unwrap(); 
                 }
             }
         }
         None => { 
             bot.send_message(
                 msg.chat.id, 
-                format!("Не найдена запись с вашим id в системе бота. Попробуйте заново использовать команду `/start` или обратитесь к автору этого ужаса, если это не помогло.")).await.unwrap(); 
+                "Не найдена запись с вашим id в системе бота. Попробуйте заново использовать команду `/start` или обратитесь к автору этого ужаса, если это не помогло.").await.unwrap(); 
             }
     }
 }
 
 pub async fn date_birth(bot: Bot, msg: Message, date:String) {
-    let date_parsed = NaiveDate::parse_from_str(&date, "%d.%m.%Y");
-    if date_parsed.is_err() {
-        bot.send_message(msg.chat.id, format!("Дата рождения должена быть указана в формате ДД.ММ.ГГГГ без дополнительных символов и пробелов.")).await.unwrap();
-    }
-    let q = Info::find().filter(info::Column::ChatId.eq(msg.chat.id.0)).one(DB.get().unwrap()).await.unwrap();
+    let date_parsed = match parse_birth_date(&date) {
+        Ok(date) => date,
+        Err(err) => {
+            bot.send_message(msg.chat.id, format!("{} \nНапример: `/datebirth 19.11.2001`.", err)).await.unwrap();
+            return;
+        }
+    };
+    let q = active_profile(msg.chat.id.0).await.unwrap();
     match q {
         Some(v) => {
             let mut nv: info::ActiveModel = v.into();
-            nv.date_birth = ActiveValue::Set(Some(date_parsed.unwrap()));
+            nv.date_birth = ActiveValue::Set(Some(date_parsed));
+            nv.verified = ActiveValue::Set(false);
+            reset_failures(&mut nv);
+            hold_for_verification(&mut nv);
             let updated = nv.update(DB.get().unwrap()).await;
             match updated {
-                Ok(_) => {
-                    bot.send_message(msg.chat.id, format!("Вашa новая дата рождения {date}.")).await.unwrap();
+                Ok(profile) => {
+                    bot.send_message(msg.chat.id, format!("Ваша новая дата рождения {}.", date_parsed.format("%d.%m.%Y"))).await.unwrap();
+                    verify_and_report(&bot, &profile).await;
                 },
                 Err(_) => {
-                    bot.send_message(msg.chat.id, format!("Не удалось обновить вашу дату рождения. Попробуйте позже или обратитесь к автору этого безобразия.")).await.unwrap();
+                    bot.send_message(msg.chat.id, "Не удалось обновить вашу дату рождения. Попробуйте позже или обратитесь к автору этого безобразия.").await.unwrap();
                 }
             }
         }
         None => {
-            bot.send_message(msg.chat.id, format!("Не найдена запись с вашим id в системе бота. Попробуйте заново использовать команду `/start` или обратитесь к автору этого ужаса, если это не помогло.")).await.unwrap();
+            bot.send_message(msg.chat.id, "Не найдена запись с вашим id в системе бота. Попробуйте заново использовать команду `/start` или обратитесь к автору этого ужаса, если это не помогло.").await.unwrap();
         }
     }
 } 
 
 pub async fn info(bot: Bot, msg: Message) {
-    let q = Info::find().filter(info::Column::ChatId.eq(msg.chat.id.0)).one(DB.get().unwrap()).await.unwrap();
+    let q = active_profile(msg.chat.id.0).await.unwrap();
     match q {
         Some(v) => {
-            bot.send_message(
-                msg.chat.id, 
-                format!(
-                    "Полис ОМС: {}; \nДата рождения: {}.", 
-                    v.oms_card.map_or("не указан".to_string(), |s| s.to_string()), 
-                    v.date_birth.map_or("не указан".to_string(), |d| d.format("%d.%m.%Y").to_string())
-                )
-            ).await.unwrap();
+            let mut text = format!(
+                "Полис ОМС: {}; \nДата рождения: {}.", 
+                v.oms_card.map_or("не указан".to_string(), oms::format), 
+                v.date_birth.map_or("не указан".to_string(), |d| d.format("%d.%m.%Y").to_string())
+            );
+            if v.oms_card.is_some() && v.date_birth.is_some() && !v.verified {
+                match v.polling_paused {
+                    true => text.push_str("\nЕМИАС не узнал пациента по этим данным, поэтому бот не следит за направлениями. Исправьте полис (`/omscard`) или дату рождения (`/datebirth`) — проверка запустится сразу."),
+                    false => text.push_str("\nДанные пока не удалось проверить в ЕМИАС: бот повторит проверку сам и начнёт следить за направлениями, когда она пройдёт.")
+                }
+            } else if v.polling_paused {
+                text.push_str(&format!(
+                    "\nПроверка направлений приостановлена: ЕМИАС {} раз подряд не принял данные пациента. Исправьте полис (`/omscard`) или дату рождения (`/datebirth`), чтобы возобновить её.", 
+                    v.profile_failure_count
+                ));
+            }
+            bot.send_message(msg.chat.id, text).await.unwrap();
         },
         None => { 
-            bot.send_message(msg.chat.id, format!("Не найдена запись с вашим id в системе бота. Попробуйте заново использовать команду `/start` или обратитесь к автору этого ужаса, если это не помогло.")).await.unwrap();
+            bot.send_message(msg.chat.id, "Не найдена запись с вашим id в системе бота. Попробуйте заново использовать команду `/start` или обратитесь к автору этого ужаса, если это не помогло.").await.unwrap();
+        }
+    }
+}
+
+pub async fn appointments(bot: Bot, msg: Message) {
+    let q = active_profile(msg.chat.id.0).await.unwrap();
+    match q {
+        Some(v) if v.oms_card.is_some() && v.date_birth.is_some() => {
+            let apps_result = EMIAS.get().unwrap().get_appointments_info(&oms::format(v.oms_card.unwrap()), &v.date_birth.unwrap()).await;
+            match apps_result {
+                Ok(appointments) => {
+                    let markup = appointments_markup(&appointments.result.appointment, 0);
+                    bot.send_message(msg.chat.id, collect_appointments_data(&appointments.result.appointment)).reply_markup(markup).await.unwrap();
+                },
+                Err(err) => {
+                    bot.send_message(msg.chat.id, format!("Не удалось получить список записей. \n{}", err.user_message())).await.unwrap();
+                }
+            }
+        },
+        Some(_) => {
+            bot.send_message(msg.chat.id, "Сначала укажите полис ОМС и дату рождения командами `/omscard` и `/datebirth`.").await.unwrap();
+        },
+        None => {
+            bot.send_message(msg.chat.id, "Не найдена запись с вашим id в системе бота. Попробуйте заново использовать команду `/start` или обратитесь к автору этого ужаса, если это не помогло.").await.unwrap();
+        }
+    }
+}
+
+pub async fn watch(bot: Bot, msg: Message, args: String) {
+    let q = active_profile(msg.chat.id.0).await.unwrap();
+    let Some(user) = q else {
+        bot.send_message(msg.chat.id, "Не найдена запись с вашим id в системе бота. Попробуйте заново использовать команду `/start` или обратитесь к автору этого ужаса, если это не помогло.").await.unwrap();
+        return;
+    };
+
+    if args.trim().is_empty() {
+        let rules = WatchRule::find().filter(watch_rule::Column::InfoId.eq(user.id)).all(DB.get().unwrap()).await.unwrap();
+        bot.send_message(msg.chat.id, format!("{}\n{}", collect_watch_rules_data(&rules), WATCH_HELP)).reply_markup(watch_rules_markup(&rules, 0)).await.unwrap();
+        return;
+    }
+
+    let draft = match parse_watch_rule(&args) {
+        Ok(draft) => draft,
+        Err(err) => {
+            bot.send_message(msg.chat.id, format!("{} \n{}", err, WATCH_HELP)).await.unwrap();
+            return;
+        }
+    };
+
+    if draft.auto_book {
+        let active = WatchRule::find()
+            .filter(watch_rule::Column::InfoId.eq(user.id))
+            .filter(watch_rule::Column::ReferralId.eq(draft.referral_id.map(|v| v as i64)))
+            .filter(watch_rule::Column::AutoBook.eq(true))
+            .one(DB.get().unwrap())
+            .await.unwrap();
+
+        if let Some(rule) = active {
+            bot.send_message(msg.chat.id, format!("По этому направлению уже включена автозапись (правило #{}). Удалите его через `/watch`, чтобы задать новое.", rule.id)).await.unwrap();
+            return;
+        }
+    }
+
+    let inserted = watch_rule::ActiveModel {
+        info_id: ActiveValue::Set(user.id),
+        referral_id: ActiveValue::Set(draft.referral_id.map(|v| v as i64)),
+        doctor_id: ActiveValue::Set(draft.doctor_id.map(|v| v as i64)),
+        doctor_name: ActiveValue::Set(draft.doctor_name),
+        lpu_id: ActiveValue::Set(draft.lpu_id.map(|v| v as i64)),
+        date_from: ActiveValue::Set(draft.date_from),
+        date_to: ActiveValue::Set(draft.date_to),
+        auto_book: ActiveValue::Set(draft.auto_book),
+        created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+        ..Default::default()
+    }.insert(DB.get().unwrap()).await;
+
+    match inserted {
+        Ok(rule) => {
+            bot.send_message(msg.chat.id, format!("Правило #{} добавлено: {}.", rule.id, rule.description())).await.unwrap();
+        },
+        Err(_) => {
+            bot.send_message(msg.chat.id, "Не удалось добавить правило. Попробуйте позже или обратитесь к автору этого безобразия.").await.unwrap();
+        }
+    }
+}
+
+pub async fn profiles(bot: Bot, msg: Message) {
+    let profiles = chat_profiles(msg.chat.id.0).await.unwrap();
+    bot.send_message(msg.chat.id, collect_profiles_data(&profiles)).reply_markup(profiles_markup(&profiles, 0)).await.unwrap();
+}
+
+pub async fn add_profile_cmd(bot: Bot, msg: Message, dialogue: OnboardingDialogue, name: String) {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 32 {
+        bot.send_message(msg.chat.id, "Укажите имя профиля длиной до 32 символов, например: `/addprofile Мама`.").await.unwrap();
+        return;
+    }
+
+    let profiles = chat_profiles(msg.chat.id.0).await.unwrap();
+    if profiles.iter().any(|profile| display_name(profile).to_lowercase() == name.to_lowercase()) {
+        bot.send_message(msg.chat.id, format!("Профиль «{}» уже есть. Список профилей — `/profiles`.", name)).await.unwrap();
+        return;
+    }
+
+    match add_profile(msg.chat.id.0, name).await {
+        Ok(profile) => {
+            bot.send_message(msg.chat.id, format!("Профиль «{}» создан и выбран активным.", display_name(&profile))).await.unwrap();
+            onboarding::begin(&bot, &dialogue, &profile).await.unwrap();
+        },
+        Err(_) => {
+            bot.send_message(msg.chat.id, "Не удалось создать профиль. Попробуйте позже или обратитесь к автору этого безобразия.").await.unwrap();
         }
     }
 }
\ No newline at end of file
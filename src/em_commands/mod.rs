@@ -1,3 +1,11 @@
 pub mod message;
 
-pub mod callback;
\ No newline at end of file
+pub mod callback;
+
+pub mod callback_data;
+
+pub mod callback_session;
+
+pub mod pagination;
+
+pub mod onboarding;
@@ -0,0 +1,203 @@
+use chrono::NaiveDate;
+use sea_orm::{prelude::*, ActiveValue};
+use teloxide::{
+    dispatching::dialogue::InMemStorage,
+    prelude::*,
+    types::InlineKeyboardMarkup,
+};
+
+use crate::em_commands::callback_data::{button, CallbackAction, CallbackData};
+use crate::entities::info;
+use crate::oms;
+use crate::poller::{hold_for_verification, reset_failures};
+use crate::profiles::{chat_profile, display_name};
+use crate::verification::verify_and_report;
+use crate::DB;
+
+/// Шаги знакомства с ботом: полис ОМС → дата рождения → подтверждение.
+/// Данные пишутся в профиль `profile_id` только после подтверждения.
+#[derive(Clone, Default, Debug)]
+pub enum Onboarding {
+    #[default]
+    Idle,
+    ReceiveOms { profile_id: i32 },
+    ReceiveBirthDate { profile_id: i32, oms: i64 },
+    Confirm { profile_id: i32, oms: i64, birth_date: NaiveDate },
+}
+
+pub type OnboardingDialogue = Dialogue<Onboarding, InMemStorage<Onboarding>>;
+
+pub type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// Проверяет дату рождения в формате ДД.ММ.ГГГГ: дата не из будущего и не старше 150 лет.
+pub fn parse_birth_date(text: &str) -> Result<NaiveDate, String> {
+    let date = NaiveDate::parse_from_str(text.trim(), "%d.%m.%Y")
+        .map_err(|_| "Дата рождения должна быть указана в формате ДД.ММ.ГГГГ, например 19.11.2001.".to_string())?;
+
+    let today = chrono::Local::now().date_naive();
+    if date > today {
+        return Err("Дата рождения не может быть в будущем.".to_string());
+    }
+    if today.years_since(date).is_none_or(|years| years > 150) {
+        return Err("Проверьте год рождения: получается больше 150 лет.".to_string());
+    }
+
+    Ok(date)
+}
+
+fn cancel_markup() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[button("Отмена", CallbackAction::OnboardingCancel)]])
+}
+
+/// Начинает знакомство для профиля: спрашивает номер полиса.
+pub async fn begin(bot: &Bot, dialogue: &OnboardingDialogue, profile: &info::Model) -> HandlerResult {
+    dialogue.update(Onboarding::ReceiveOms { profile_id: profile.id }).await?;
+    bot.send_message(
+        dialogue.chat_id(),
+        format!(
+            "Профиль «{}». Введите единый номер полиса ОМС — 16 цифр, пробелы и дефисы допустимы. \nОтменить можно в любой момент командой `/cancel`.",
+            display_name(profile)
+        )
+    ).reply_markup(cancel_markup()).await?;
+
+    Ok(())
+}
+
+pub async fn receive_oms(bot: Bot, dialogue: OnboardingDialogue, profile_id: i32, msg: Message) -> HandlerResult {
+    match oms::parse(msg.text().unwrap_or_default()) {
+        Ok(oms) => {
+            dialogue.update(Onboarding::ReceiveBirthDate { profile_id, oms }).await?;
+            bot.send_message(msg.chat.id, "Теперь введите дату рождения в формате ДД.ММ.ГГГГ.").reply_markup(cancel_markup()).await?;
+        },
+        Err(err) => {
+            bot.send_message(msg.chat.id, format!("{} \nПопробуйте ещё раз или отмените командой `/cancel`.", err)).reply_markup(cancel_markup()).await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn receive_birth_date(bot: Bot, dialogue: OnboardingDialogue, (profile_id, oms): (i32, i64), msg: Message) -> HandlerResult {
+    match parse_birth_date(msg.text().unwrap_or_default()) {
+        Ok(birth_date) => {
+            dialogue.update(Onboarding::Confirm { profile_id, oms, birth_date }).await?;
+
+            let markup = InlineKeyboardMarkup::new([
+                [button("Сохранить", CallbackAction::OnboardingSave)],
+                [button("Ввести заново", CallbackAction::OnboardingRestart)],
+                [button("Отмена", CallbackAction::OnboardingCancel)],
+            ]);
+            bot.send_message(
+                msg.chat.id,
+                format!("Проверьте данные: \nПолис ОМС: {}; \nДата рождения: {}. \nВсё верно?", oms::format(oms), birth_date.format("%d.%m.%Y"))
+            ).reply_markup(markup).await?;
+        },
+        Err(err) => {
+            bot.send_message(msg.chat.id, format!("{} \nПопробуйте ещё раз или отмените командой `/cancel`.", err)).reply_markup(cancel_markup()).await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn waiting_confirm(bot: Bot, msg: Message) -> HandlerResult {
+    bot.send_message(msg.chat.id, "Подтвердите данные кнопками выше или отмените командой `/cancel`.").await?;
+    Ok(())
+}
+
+pub async fn cancel(bot: Bot, dialogue: OnboardingDialogue, msg: Message) -> HandlerResult {
+    dialogue.exit().await?;
+    bot.send_message(msg.chat.id, "Ввод данных отменён. Начать заново можно командой `/start`.").await?;
+    Ok(())
+}
+
+/// Кнопки знакомства с ботом: сохранить, ввести заново, отменить.
+pub async fn callback(bot: Bot, dialogue: OnboardingDialogue, callback: CallbackQuery, data: CallbackData) -> HandlerResult {
+    bot.answer_callback_query(&callback.id).await?;
+
+    let chat_id = dialogue.chat_id();
+    let state = dialogue.get().await?.unwrap_or_default();
+
+    match (data.action, state) {
+        (CallbackAction::OnboardingCancel, _) => {
+            dialogue.exit().await?;
+            bot.send_message(chat_id, "Ввод данных отменён. Начать заново можно командой `/start`.").await?;
+        },
+        (CallbackAction::OnboardingRestart, Onboarding::Confirm { profile_id, .. }) => {
+            match chat_profile(chat_id.0, profile_id).await? {
+                Some(profile) => begin(&bot, &dialogue, &profile).await?,
+                None => dialogue.exit().await?
+            }
+        },
+        (CallbackAction::OnboardingSave, Onboarding::Confirm { profile_id, oms, birth_date }) => {
+            dialogue.exit().await?;
+
+            let Some(profile) = chat_profile(chat_id.0, profile_id).await? else {
+                bot.send_message(chat_id, "Профиль не найден. Возможно, он уже удалён.").await?;
+                return Ok(());
+            };
+
+            let name = display_name(&profile);
+            let mut profile: info::ActiveModel = profile.into();
+            profile.oms_card = ActiveValue::Set(Some(oms));
+            profile.date_birth = ActiveValue::Set(Some(birth_date));
+            profile.verified = ActiveValue::Set(false);
+            reset_failures(&mut profile);
+            hold_for_verification(&mut profile);
+
+            match profile.update(DB.get().unwrap()).await {
+                Ok(profile) => {
+                    bot.send_message(
+                        chat_id,
+                        format!("Данные профиля «{}» сохранены, проверяем их в ЕМИАС. Список команд — `/help`.", name)
+                    ).await?;
+                    verify_and_report(&bot, &profile).await;
+                },
+                Err(_) => {
+                    bot.send_message(chat_id, "Не удалось сохранить данные. Попробуйте позже или обратитесь к автору этого безобразия.").await?;
+                }
+            }
+        },
+        _ => {
+            bot.send_message(chat_id, "Эта кнопка уже неактуальна. Начать заново можно командой `/start`.").await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(date: NaiveDate) -> String {
+        date.format("%d.%m.%Y").to_string()
+    }
+
+    #[test]
+    fn parses_valid_date() {
+        assert_eq!(parse_birth_date("19.11.2001"), Ok(NaiveDate::from_ymd_opt(2001, 11, 19).unwrap()));
+        assert_eq!(parse_birth_date(" 01.02.1990\n"), Ok(NaiveDate::from_ymd_opt(1990, 2, 1).unwrap()));
+    }
+
+    #[test]
+    fn rejects_future_date() {
+        let tomorrow = chrono::Local::now().date_naive().succ_opt().unwrap();
+        assert_eq!(parse_birth_date(&format(tomorrow)), Err("Дата рождения не может быть в будущем.".to_string()));
+    }
+
+    #[test]
+    fn rejects_more_than_150_years_ago() {
+        let today = chrono::Local::now().date_naive();
+        let older = today - chrono::Months::new(151 * 12);
+        assert!(parse_birth_date(&format(older + chrono::Days::new(1))).is_ok());
+        assert_eq!(parse_birth_date(&format(older)), Err("Проверьте год рождения: получается больше 150 лет.".to_string()));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for text in ["", "19/11/2001", "2001-11-19", "31.02.2001", "19.11.01x", "завтра"] {
+            assert!(parse_birth_date(text).is_err(), "{text:?}");
+        }
+    }
+}
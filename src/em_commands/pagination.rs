@@ -0,0 +1,161 @@
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::em_commands::callback_data::{button, CallbackAction};
+
+/// Сколько строк списка помещается на одной странице клавиатуры.
+pub const PAGE_ROWS: usize = 8;
+
+/// Клавиатура списка (направления, врачи, дни, слоты, записи), разбитая на страницы.
+/// Под строками текущей страницы идёт навигация «◀ 2/5 ▶», а под ней — постоянные кнопки
+/// вроде «Обновить» и «Назад». Если страница одна, навигации нет.
+///
+/// Строка кнопок собирается функцией `row` только для элементов показываемой страницы:
+/// кнопка с длинными данными занимает место в хранилище сессий, и на невидимые страницы
+/// его тратить незачем.
+pub struct PaginatedKeyboard<T, F> {
+    items: Vec<T>,
+    row: F,
+    page_rows: usize,
+    empty: Option<String>,
+    footer: Vec<Vec<InlineKeyboardButton>>,
+}
+
+impl<T, F> PaginatedKeyboard<T, F>
+where
+    F: Fn(&T) -> Vec<InlineKeyboardButton>
+{
+    pub fn new(items: impl IntoIterator<Item = T>, row: F) -> Self {
+        Self { items: items.into_iter().collect(), row, page_rows: PAGE_ROWS, empty: None, footer: vec![] }
+    }
+
+    pub fn page_rows(mut self, page_rows: usize) -> Self {
+        self.page_rows = page_rows.max(1);
+        self
+    }
+
+    /// Подпись, которая показывается вместо пустого списка.
+    pub fn empty(mut self, text: impl Into<String>) -> Self {
+        self.empty = Some(text.into());
+        self
+    }
+
+    /// Строка кнопок под списком, одинаковая на всех страницах.
+    pub fn footer(mut self, row: Vec<InlineKeyboardButton>) -> Self {
+        self.footer.push(row);
+        self
+    }
+
+    pub fn pages(&self) -> usize {
+        self.items.len().div_ceil(self.page_rows).max(1)
+    }
+
+    /// Собирает страницу `page` (номер с нуля; слишком большой номер — последняя страница,
+    /// например после удаления элемента). `page_action` — действие кнопок перехода на страницу.
+    pub fn build(self, page: usize, page_action: impl Fn(usize) -> CallbackAction) -> InlineKeyboardMarkup {
+        let pages = self.pages();
+        let page = page.min(pages - 1);
+
+        let mut keyboard = self.items
+            .iter()
+            .skip(page * self.page_rows)
+            .take(self.page_rows)
+            .map(&self.row)
+            .collect::<Vec<Vec<InlineKeyboardButton>>>();
+
+        if keyboard.is_empty() {
+            if let Some(empty) = self.empty {
+                keyboard.push(vec![button(empty, CallbackAction::Noop)]);
+            }
+        }
+
+        if pages > 1 {
+            let previous = match page {
+                0 => button("·", CallbackAction::Noop),
+                _ => button("◀", page_action(page - 1))
+            };
+            let next = match page + 1 < pages {
+                true => button("▶", page_action(page + 1)),
+                false => button("·", CallbackAction::Noop)
+            };
+            keyboard.push(vec![previous, button(format!("{}/{}", page + 1, pages), CallbackAction::Noop), next]);
+        }
+
+        keyboard.extend(self.footer);
+        InlineKeyboardMarkup::new(keyboard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    fn keyboard(items: usize) -> PaginatedKeyboard<usize, impl Fn(&usize) -> Vec<InlineKeyboardButton>> {
+        PaginatedKeyboard::new(0..items, |item| vec![button(item.to_string(), CallbackAction::Unwatch { rule_id: *item as i32 })])
+            .page_rows(3)
+            .empty("Пусто")
+            .footer(vec![button("Назад", CallbackAction::MainMenu)])
+    }
+
+    fn texts(markup: &InlineKeyboardMarkup) -> Vec<Vec<String>> {
+        markup.inline_keyboard.iter()
+            .map(|row| row.iter().map(|key| key.text.clone()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn builds_requested_page_with_navigation() {
+        let markup = keyboard(7).build(1, |page| CallbackAction::WatchRules { page });
+
+        assert_eq!(texts(&markup), [
+            vec!["3"], vec!["4"], vec!["5"],
+            vec!["◀", "2/3", "▶"],
+            vec!["Назад"],
+        ]);
+        assert_eq!(markup.inline_keyboard[3][0], button("◀", CallbackAction::WatchRules { page: 0 }));
+        assert_eq!(markup.inline_keyboard[3][2], button("▶", CallbackAction::WatchRules { page: 2 }));
+    }
+
+    #[test]
+    fn edge_pages_have_no_link_outside() {
+        let first = keyboard(7).build(0, |page| CallbackAction::WatchRules { page });
+        assert_eq!(texts(&first)[3], ["·", "1/3", "▶"]);
+
+        let last = keyboard(7).build(2, |page| CallbackAction::WatchRules { page });
+        assert_eq!(texts(&last), [vec!["6"], vec!["◀", "3/3", "·"], vec!["Назад"]]);
+    }
+
+    #[test]
+    fn page_past_the_end_is_clamped_to_last() {
+        let markup = keyboard(7).build(10, |page| CallbackAction::WatchRules { page });
+        assert_eq!(texts(&markup)[1], ["◀", "3/3", "·"]);
+    }
+
+    #[test]
+    fn single_page_has_no_navigation() {
+        let markup = keyboard(3).build(0, |page| CallbackAction::WatchRules { page });
+        assert_eq!(texts(&markup), [vec!["0"], vec!["1"], vec!["2"], vec!["Назад"]]);
+    }
+
+    #[test]
+    fn empty_list_shows_placeholder() {
+        let keyboard = keyboard(0);
+        assert_eq!(keyboard.pages(), 1);
+
+        let markup = keyboard.build(0, |page| CallbackAction::WatchRules { page });
+        assert_eq!(texts(&markup), [vec!["Пусто"], vec!["Назад"]]);
+    }
+
+    #[test]
+    fn builds_rows_of_visible_page_only() {
+        let built = Cell::new(0);
+        PaginatedKeyboard::new(0..100, |_: &i32| {
+                built.set(built.get() + 1);
+                vec![]
+            })
+            .build(5, |page| CallbackAction::WatchRules { page });
+
+        assert_eq!(built.get(), PAGE_ROWS);
+    }
+}
@@ -0,0 +1,369 @@
+use std::{env, str::FromStr, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::Duration};
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::cache::{CacheKey, ResponseCache};
+use crate::error::EmiasError;
+use crate::parsable::appointments::{
+    AppointmentsInfoParamsRequest, AppointmentsInfoResponse,
+    CancelAppointmentParamsRequest, CancelAppointmentResponse,
+    CreateAppointmentParamsRequest, CreateAppointmentResponse,
+    ShiftAppointmentParamsRequest, ShiftAppointmentResponse
+};
+use crate::limiter::{RateLimiter, RetryPolicy};
+use crate::parsable::basic::{BasicRequest, BasicResponse};
+use crate::parsable::doctors::{DoctorsInfoParamsRequest, DoctorsInfoParamsResponse};
+use crate::parsable::referrals::{ReferralsInfoParamsRequest, ReferralsInfoResponse};
+use crate::parsable::schedule::{ScheduleInfoParamsRequest, ScheduleInfoResponse};
+
+pub const DEFAULT_BASE_URL: &str = "https://emias.info/api/emc/appointment-eip/v1/";
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_USER_AGENT: &str = concat!("em_bot/", env!("CARGO_PKG_VERSION"));
+
+lazy_static::lazy_static! {
+    /// Метка запуска процесса: делает id запросов уникальными и между перезапусками бота.
+    static ref RUN_ID: String = format!("{:x}", chrono::Utc::now().timestamp_millis());
+}
+
+static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Уникальный id JSON-RPC запроса вида `<метка запуска>-<порядковый номер>`.
+pub fn next_request_id() -> String {
+    format!("{}-{}", *RUN_ID, REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Настройки клиента ЕМИАС.
+#[derive(Debug, Clone)]
+pub struct EmiasConfig {
+    pub base_url: String,
+    pub timeout: Duration,
+    pub user_agent: String,
+    pub requests_per_second: f64,
+    pub max_concurrency: usize,
+    pub retry: RetryPolicy,
+    pub cache_ttl: CacheTtl,
+}
+
+/// Время жизни закэшированных ответов по методам. Нулевой TTL отключает кэш метода.
+#[derive(Debug, Clone, Copy)]
+pub struct CacheTtl {
+    pub referrals: Duration,
+    pub doctors: Duration,
+}
+
+impl Default for EmiasConfig {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            requests_per_second: 2.0,
+            max_concurrency: 4,
+            retry: RetryPolicy {
+                max_retries: 3,
+                base_delay: Duration::from_millis(500),
+                max_delay: Duration::from_secs(30),
+            },
+            cache_ttl: CacheTtl {
+                referrals: Duration::from_secs(300),
+                doctors: Duration::from_secs(120),
+            },
+        }
+    }
+}
+
+impl EmiasConfig {
+    /// Читает настройки из переменных окружения:
+    /// `EMIAS_URL`, `EMIAS_TIMEOUT` (в секундах), `EMIAS_USER_AGENT`, `EMIAS_RPS`,
+    /// `EMIAS_MAX_CONCURRENCY`, `EMIAS_MAX_RETRIES`, `EMIAS_CACHE_TTL_REFERRALS`
+    /// и `EMIAS_CACHE_TTL_DOCTORS` (в секундах).
+    /// Отсутствующие значения заменяются значениями по умолчанию.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        Self {
+            base_url: env::var("EMIAS_URL").unwrap_or(default.base_url),
+            timeout: env_parse("EMIAS_TIMEOUT").map_or(default.timeout, Duration::from_secs),
+            user_agent: env::var("EMIAS_USER_AGENT").unwrap_or(default.user_agent),
+            requests_per_second: env_parse("EMIAS_RPS").unwrap_or(default.requests_per_second),
+            max_concurrency: env_parse("EMIAS_MAX_CONCURRENCY").unwrap_or(default.max_concurrency),
+            retry: RetryPolicy {
+                max_retries: env_parse("EMIAS_MAX_RETRIES").unwrap_or(default.retry.max_retries),
+                ..default.retry
+            },
+            cache_ttl: CacheTtl {
+                referrals: env_parse("EMIAS_CACHE_TTL_REFERRALS").map_or(default.cache_ttl.referrals, Duration::from_secs),
+                doctors: env_parse("EMIAS_CACHE_TTL_DOCTORS").map_or(default.cache_ttl.doctors, Duration::from_secs),
+            },
+        }
+    }
+}
+
+fn env_parse<T: FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok().and_then(|v| v.parse().ok())
+}
+
+/// Клиент JSON-RPC API ЕМИАС.
+///
+/// Держит один `reqwest::Client` (и его пул соединений) на всё приложение,
+/// поэтому создаётся один раз и переиспользуется поллером, колбэками и командами.
+/// Через него же проходят общий лимит частоты запросов и повторы при временных сбоях.
+///
+/// Успешная запись, отмена или перенос сбрасывает кэш пациента: использованное направление
+/// и занятая ближайшая дата не должны висеть в меню до истечения TTL.
+#[derive(Debug, Clone)]
+pub struct EmiasClient {
+    http: reqwest::Client,
+    base_url: String,
+    limiter: Arc<RateLimiter>,
+    retry: RetryPolicy,
+    cache: Arc<ResponseCache>,
+    cache_ttl: CacheTtl,
+}
+
+impl EmiasClient {
+    pub fn new(config: EmiasConfig) -> Result<Self, reqwest::Error> {
+        let http = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .user_agent(config.user_agent)
+            .build()?;
+
+        Ok(Self {
+            http,
+            base_url: config.base_url,
+            limiter: Arc::new(RateLimiter::new(config.requests_per_second, config.max_concurrency)),
+            retry: config.retry,
+            cache: Arc::new(ResponseCache::default()),
+            cache_ttl: config.cache_ttl,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, reqwest::Error> {
+        Self::new(EmiasConfig::from_env())
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Сбрасывает закэшированные направления и списки врачей пациента.
+    pub fn invalidate(&self, oms_number: &str, birth_date: &NaiveDate) {
+        self.cache.invalidate(oms_number, birth_date);
+    }
+
+    pub async fn get_referrals_info(&self, oms_number: &str, birth_date: &NaiveDate) -> Result<ReferralsInfoResponse, EmiasError> {
+        let request = BasicRequest::<ReferralsInfoParamsRequest>::new(
+            Some(next_request_id()),
+            oms_number.to_owned(),
+            birth_date.to_string()
+        );
+        let key = CacheKey { oms_number: oms_number.to_owned(), birth_date: *birth_date, method: "getReferralsInfo", referral_id: None };
+
+        self.call_cached(key, self.cache_ttl.referrals, &request).await
+    }
+
+    pub async fn get_doctors_info(&self, oms_number: &str, birth_date: &NaiveDate, referral_id: u64) -> Result<DoctorsInfoParamsResponse, EmiasError> {
+        let request = BasicRequest::<DoctorsInfoParamsRequest>::new(
+            Some(next_request_id()),
+            oms_number.to_owned(),
+            birth_date.to_string(),
+            referral_id
+        );
+        let key = CacheKey { oms_number: oms_number.to_owned(), birth_date: *birth_date, method: "getDoctorsInfo", referral_id: Some(referral_id) };
+
+        self.call_cached(key, self.cache_ttl.doctors, &request).await
+    }
+
+    pub async fn get_schedule_info(
+        &self,
+        oms_number: &str,
+        birth_date: &NaiveDate,
+        available_resource_id: u64,
+        complex_resource_id: u64,
+        referral_id: Option<u64>
+    ) -> Result<ScheduleInfoResponse, EmiasError> {
+        let request = BasicRequest::<ScheduleInfoParamsRequest>::new(
+            Some(next_request_id()),
+            oms_number.to_owned(),
+            birth_date.to_string(),
+            available_resource_id,
+            complex_resource_id,
+            referral_id
+        );
+
+        self.call(&request).await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_appointment(
+        &self,
+        oms_number: &str,
+        birth_date: &NaiveDate,
+        available_resource_id: u64,
+        complex_resource_id: u64,
+        referral_id: u64,
+        start_time: &DateTime<FixedOffset>,
+        end_time: &DateTime<FixedOffset>
+    ) -> Result<CreateAppointmentResponse, EmiasError> {
+        let request = BasicRequest::<CreateAppointmentParamsRequest>::new(
+            Some(next_request_id()),
+            oms_number.to_owned(),
+            birth_date.to_string(),
+            available_resource_id,
+            complex_resource_id,
+            referral_id,
+            start_time,
+            end_time
+        );
+
+        let response = self.call(&request).await?;
+        self.cache.invalidate(oms_number, birth_date);
+
+        Ok(response)
+    }
+
+    pub async fn get_appointments_info(&self, oms_number: &str, birth_date: &NaiveDate) -> Result<AppointmentsInfoResponse, EmiasError> {
+        let request = BasicRequest::<AppointmentsInfoParamsRequest>::new(
+            Some(next_request_id()),
+            oms_number.to_owned(),
+            birth_date.to_string()
+        );
+
+        self.call(&request).await
+    }
+
+    pub async fn cancel_appointment(&self, oms_number: &str, birth_date: &NaiveDate, appointment_id: u64) -> Result<CancelAppointmentResponse, EmiasError> {
+        let request = BasicRequest::<CancelAppointmentParamsRequest>::new(
+            Some(next_request_id()),
+            oms_number.to_owned(),
+            birth_date.to_string(),
+            appointment_id
+        );
+
+        let response = self.call(&request).await?;
+        self.cache.invalidate(oms_number, birth_date);
+
+        Ok(response)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn shift_appointment(
+        &self,
+        oms_number: &str,
+        birth_date: &NaiveDate,
+        appointment_id: u64,
+        available_resource_id: u64,
+        complex_resource_id: u64,
+        referral_id: Option<u64>,
+        start_time: &DateTime<FixedOffset>,
+        end_time: &DateTime<FixedOffset>
+    ) -> Result<ShiftAppointmentResponse, EmiasError> {
+        let request = BasicRequest::<ShiftAppointmentParamsRequest>::new(
+            Some(next_request_id()),
+            oms_number.to_owned(),
+            birth_date.to_string(),
+            appointment_id,
+            available_resource_id,
+            complex_resource_id,
+            referral_id,
+            start_time,
+            end_time
+        );
+
+        let response = self.call(&request).await?;
+        self.cache.invalidate(oms_number, birth_date);
+
+        Ok(response)
+    }
+
+    /// Как [`Self::call`], но сначала ищет ответ в кэше и кладёт туда успешный ответ на `ttl`.
+    async fn call_cached<P: Serialize, R: DeserializeOwned>(&self, key: CacheKey, ttl: Duration, request: &BasicRequest<P>) -> Result<R, EmiasError> {
+        if let Some(body) = self.cache.get(&key) {
+            if let Ok(response) = serde_json::from_slice::<R>(&body) {
+                log::debug!("{} served from cache", request.method);
+                return Ok(response);
+            }
+        }
+
+        let request_id = request.id.clone().unwrap_or_default();
+        let body = self.call_raw(&request_id, request).await?;
+        let response = parse_body(&request_id, &body)?;
+        self.cache.insert(key, body, ttl);
+
+        Ok(response)
+    }
+
+    async fn call<P: Serialize, R: DeserializeOwned>(&self, request: &BasicRequest<P>) -> Result<R, EmiasError> {
+        let request_id = request.id.clone().unwrap_or_default();
+        let body = self.call_raw(&request_id, request).await?;
+
+        parse_body(&request_id, &body)
+    }
+
+    async fn call_raw<P: Serialize>(&self, request_id: &str, request: &BasicRequest<P>) -> Result<Vec<u8>, EmiasError> {
+        log::debug!("[{}] -> {}", request_id, request.method);
+
+        let idempotent = request.method.starts_with("get");
+        let mut attempt = 0;
+        loop {
+            let result = {
+                let _permit = self.limiter.acquire().await;
+                self.send(request_id, request).await
+            };
+
+            match result {
+                Ok(body) => {
+                    log::debug!("[{}] <- {}: ok", request_id, request.method);
+                    return Ok(body);
+                },
+                Err(err) if err.is_retryable(idempotent) && attempt < self.retry.max_retries => {
+                    let delay = self.retry.delay(attempt);
+                    log::info!("{} ({}); retry {} in {:?}", err, request.method, attempt + 1, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                },
+                Err(err) => {
+                    log::warn!("{} ({})", err, request.method);
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Отправляет запрос и возвращает тело ответа, если в нём нет JSON-RPC ошибки
+    /// и `id` совпадает с `id` запроса.
+    async fn send<P: Serialize>(&self, request_id: &str, request: &BasicRequest<P>) -> Result<Vec<u8>, EmiasError> {
+        let transport = |source| EmiasError::Transport { request_id: request_id.to_owned(), source };
+
+        let response = self.http
+            .post(format!("{}?{}", self.base_url, request.method))
+            .json(request)
+            .send()
+            .await
+            .map_err(transport)?;
+
+        let status = response.status();
+        let body = response.bytes().await.map_err(transport)?;
+
+        match serde_json::from_slice::<BasicResponse>(&body) {
+            Ok(BasicResponse { error: Some(error), .. }) => {
+                Err(EmiasError::Rpc { request_id: request_id.to_owned(), code: error.code, message: error.message })
+            },
+            _ if !status.is_success() => {
+                Err(EmiasError::Status { request_id: request_id.to_owned(), status })
+            },
+            Err(err) => {
+                Err(EmiasError::Schema { request_id: request_id.to_owned(), details: err.to_string() })
+            },
+            Ok(BasicResponse { id, .. }) if id.as_deref() != Some(request_id) => {
+                Err(EmiasError::IdMismatch { request_id: request_id.to_owned(), response_id: id })
+            },
+            Ok(_) => Ok(body.to_vec())
+        }
+    }
+}
+
+fn parse_body<R: DeserializeOwned>(request_id: &str, body: &[u8]) -> Result<R, EmiasError> {
+    serde_json::from_slice::<R>(body).map_err(|err| EmiasError::Schema { request_id: request_id.to_owned(), details: err.to_string() })
+}
@@ -10,9 +10,55 @@ pub struct Model {
     pub chat_id: i64,
     pub oms_card: Option<i64>,
     pub date_birth: Option<Date>,
+    pub last_polled_at: Option<DateTime>,
+    pub next_poll_at: Option<DateTime>,
+    pub failure_count: i32,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub last_error: Option<String>,
+    pub last_failed_at: Option<DateTime>,
+    pub failure_notified: bool,
+    pub polling_paused: bool,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub name: Option<String>,
+    pub is_active: bool,
+    pub verified: bool,
+    pub profile_failure_count: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::appointment_reminder::Entity")]
+    AppointmentReminder,
+    #[sea_orm(has_many = "super::availability::Entity")]
+    Availability,
+    #[sea_orm(has_many = "super::referral_reminder::Entity")]
+    ReferralReminder,
+    #[sea_orm(has_many = "super::watch_rule::Entity")]
+    WatchRule,
+}
+
+impl Related<super::appointment_reminder::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AppointmentReminder.def()
+    }
+}
+
+impl Related<super::availability::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Availability.def()
+    }
+}
+
+impl Related<super::referral_reminder::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReferralReminder.def()
+    }
+}
+
+impl Related<super::watch_rule::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::WatchRule.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -2,4 +2,9 @@
 
 pub mod prelude;
 
+pub mod appointment_reminder;
+pub mod availability;
+pub mod callback_session;
 pub mod info;
+pub mod referral_reminder;
+pub mod watch_rule;
@@ -1,3 +1,8 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.1
 
+pub use super::appointment_reminder::Entity as AppointmentReminder;
+pub use super::availability::Entity as Availability;
+pub use super::callback_session::Entity as CallbackSession;
 pub use super::info::Entity as Info;
+pub use super::referral_reminder::Entity as ReferralReminder;
+pub use super::watch_rule::Entity as WatchRule;
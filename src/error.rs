@@ -0,0 +1,176 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+
+/// Ошибка обращения к API ЕМИАС.
+///
+/// Каждый вариант несёт `request_id` — идентификатор JSON-RPC запроса, по которому
+/// можно найти конкретный обмен с ЕМИАС в логах бота.
+#[derive(Debug)]
+pub enum EmiasError {
+    /// Запрос не дошёл до ЕМИАС или ответ не удалось дочитать (сеть, таймаут, TLS).
+    Transport { request_id: String, source: reqwest::Error },
+    /// ЕМИАС ответил HTTP-статусом, отличным от 2xx, без JSON-RPC ошибки в теле.
+    Status { request_id: String, status: StatusCode },
+    /// ЕМИАС вернул JSON-RPC объект `error`.
+    Rpc { request_id: String, code: i64, message: String },
+    /// Ответ не соответствует ожидаемой схеме.
+    Schema { request_id: String, details: String },
+    /// `id` ответа не совпадает с `id` запроса.
+    IdMismatch { request_id: String, response_id: Option<String> },
+}
+
+/// Смысл JSON-RPC ошибки ЕМИАС. Полного списка кодов ЕМИАС не публикует, поэтому сначала
+/// смотрим на известные коды, а если код незнаком — на текст сообщения.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcErrorKind {
+    /// Пациент не найден или ЕМИАС не принял полис или дату рождения.
+    Patient,
+    /// Выбранное время уже занято или больше недоступно для записи.
+    SlotUnavailable,
+    /// Технические работы или внутренняя ошибка ЕМИАС: стоит повторить позже.
+    Maintenance,
+    /// Остальные отказы, например истёкшее направление.
+    Other,
+}
+
+/// Пациент с такими полисом и датой рождения не найден.
+pub const RPC_PATIENT_NOT_FOUND: i64 = 1001;
+/// Неверные параметры запроса (JSON-RPC): ЕМИАС так отвечает на полис или дату рождения
+/// в неправильном формате.
+pub const RPC_INVALID_PARAMS: i64 = -32602;
+/// Внутренняя ошибка сервера (JSON-RPC).
+pub const RPC_INTERNAL_ERROR: i64 = -32603;
+/// Диапазон серверных ошибок JSON-RPC.
+pub const RPC_SERVER_ERRORS: std::ops::RangeInclusive<i64> = -32099..=-32000;
+
+impl RpcErrorKind {
+    pub fn classify(code: i64, message: &str) -> Self {
+        match code {
+            RPC_PATIENT_NOT_FOUND | RPC_INVALID_PARAMS => return RpcErrorKind::Patient,
+            RPC_INTERNAL_ERROR => return RpcErrorKind::Maintenance,
+            code if RPC_SERVER_ERRORS.contains(&code) => return RpcErrorKind::Maintenance,
+            _ => {}
+        }
+
+        let message = message.to_lowercase();
+        let mentions = |words: &[&str]| words.iter().any(|word| message.contains(word));
+
+        if mentions(&["технич", "профилакт", "временно недоступ", "повторите попытку позже"]) {
+            RpcErrorKind::Maintenance
+        } else if mentions(&["занят", "слот", "недоступно для записи"]) {
+            RpcErrorKind::SlotUnavailable
+        } else if mentions(&["пациент", "полис", "омс", "дата рождения", "дату рождения"]) {
+            RpcErrorKind::Patient
+        } else {
+            RpcErrorKind::Other
+        }
+    }
+}
+
+impl EmiasError {
+    pub fn request_id(&self) -> &str {
+        match self {
+            EmiasError::Transport { request_id, .. }
+            | EmiasError::Status { request_id, .. }
+            | EmiasError::Rpc { request_id, .. }
+            | EmiasError::Schema { request_id, .. }
+            | EmiasError::IdMismatch { request_id, .. } => request_id
+        }
+    }
+
+    /// Имеет ли смысл повторить запрос. Для идемпотентных методов (`get*`) повторяются
+    /// все временные сбои: таймауты, ошибки соединения, ответы 5xx и 429 Too Many Requests.
+    /// Запись, перенос и отмену повторяем только если запрос точно не был обработан,
+    /// иначе можно записаться дважды.
+    pub fn is_retryable(&self, idempotent: bool) -> bool {
+        match self {
+            EmiasError::Transport { source, .. } if source.is_connect() => true,
+            EmiasError::Transport { source, .. } => idempotent && source.is_timeout(),
+            EmiasError::Status { status, .. } if *status == StatusCode::TOO_MANY_REQUESTS => true,
+            EmiasError::Status { status, .. } => idempotent && status.is_server_error(),
+            _ => false
+        }
+    }
+
+    /// Смысл JSON-RPC ошибки; `None` для сбоев сети, HTTP и формата ответа.
+    pub fn rpc_kind(&self) -> Option<RpcErrorKind> {
+        match self {
+            EmiasError::Rpc { code, message, .. } => Some(RpcErrorKind::classify(*code, message)),
+            _ => None
+        }
+    }
+
+    /// ЕМИАС не принял данные пациента (неверный полис или дата рождения), а не временный
+    /// сбой сети или сервиса. Повтор её не исправит.
+    pub fn is_profile_error(&self) -> bool {
+        self.rpc_kind() == Some(RpcErrorKind::Patient)
+    }
+
+    /// ЕМИАС отказал в записи или переносе, потому что выбранное время уже занято.
+    pub fn is_slot_unavailable(&self) -> bool {
+        self.rpc_kind() == Some(RpcErrorKind::SlotUnavailable)
+    }
+
+    /// Пояснение для пользователя бота.
+    pub fn user_message(&self) -> String {
+        let explanation = match self {
+            EmiasError::Transport { source, .. } if source.is_timeout() => {
+                "ЕМИАС не ответил вовремя. Попробуйте позже.".to_string()
+            },
+            EmiasError::Transport { .. } => {
+                "Не удалось связаться с ЕМИАС. Попробуйте позже.".to_string()
+            },
+            EmiasError::Status { status, .. } if status.is_server_error() => {
+                format!("Сервис ЕМИАС временно недоступен (код ответа {}). Попробуйте позже.", status.as_u16())
+            },
+            EmiasError::Status { status, .. } => {
+                format!("ЕМИАС отклонил запрос (код ответа {}).", status.as_u16())
+            },
+            EmiasError::Rpc { code, message, .. } => {
+                let reason = match message.is_empty() {
+                    true => format!("ЕМИАС вернул ошибку {} без пояснения.", code),
+                    false => format!("ЕМИАС сообщает: «{}».", message)
+                };
+                let advice = match RpcErrorKind::classify(*code, message) {
+                    RpcErrorKind::Patient => "Проверьте полис ОМС (`/omscard`) и дату рождения (`/datebirth`).",
+                    RpcErrorKind::SlotUnavailable => "Выбранное время уже занято или больше недоступно. Выберите другое время.",
+                    RpcErrorKind::Maintenance => "На ЕМИАС, похоже, ведутся работы. Попробуйте позже.",
+                    RpcErrorKind::Other => "Если ошибка повторяется, попробуйте позже или обратитесь к автору бота."
+                };
+                format!("{} {}", reason, advice)
+            },
+            EmiasError::Schema { .. } => {
+                "ЕМИАС прислал ответ в неожиданном формате. Возможно, сервис изменился или на нём ведутся работы.".to_string()
+            },
+            EmiasError::IdMismatch { .. } => {
+                "ЕМИАС прислал ответ на другой запрос. Попробуйте ещё раз.".to_string()
+            }
+        };
+
+        format!("{} \nКод запроса: `{}`.", explanation, self.request_id())
+    }
+}
+
+impl fmt::Display for EmiasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmiasError::Transport { request_id, source } => write!(f, "[{}] transport error: {}", request_id, source),
+            EmiasError::Status { request_id, status } => write!(f, "[{}] unexpected HTTP status: {}", request_id, status),
+            EmiasError::Rpc { request_id, code, message } => write!(f, "[{}] JSON-RPC error {}: {}", request_id, code, message),
+            EmiasError::Schema { request_id, details } => write!(f, "[{}] unexpected response schema: {}", request_id, details),
+            EmiasError::IdMismatch { request_id, response_id } => {
+                write!(f, "[{}] response id mismatch: got {:?}", request_id, response_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmiasError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmiasError::Transport { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
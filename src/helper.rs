@@ -1,138 +1,302 @@
-use crate::parsable::basic::BasicRequest;
-use crate::parsable::doctors::{self, DoctorsInfoParamsRequest, DoctorsInfoParamsResponse, HasComplexResource};
-use crate::parsable::referrals::{ReferralsInfoParamsRequest, ReferralsInfoResponse};
+use crate::error::EmiasError;
+use crate::parsable::appointments::AppointmentInfo;
+use crate::parsable::doctors::{self, Room};
+use crate::parsable::referrals::ReferralInfo;
+use crate::parsable::schedule::{ScheduleInfo, ScheduleInfoResponse, Slot};
 
 use crate::entities::info::Model;
+use crate::{oms, EMIAS};
 
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime};
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 
-pub async fn get_user_referrals(user: &Model) -> Result<String, reqwest::Error> {
+pub fn collect_schedule_data(schedule: &ScheduleInfo) -> String {
+    let mut schedule_string = String::new();
 
-    let ref_res = get_referrals_obj(user).await;
+    for day in &schedule.schedule_of_day {
+        let times = day.slots()
+            .map(|slot| slot.start_time.format("%H:%M").to_string())
+            .collect::<Vec<String>>();
 
-    match ref_res {
-        Ok(referrals) => {
-            let mut message_string = "Ваши направления: \n".to_string();
+        if times.is_empty() {
+            continue;
+        }
+
+        schedule_string.push_str(&format!("[{}]: {}\n", day.date.format("%d.%m.%Y"), times.join(", ")));
+    }
+
+    if schedule_string.is_empty() {
+        "Нет свободного времени для записи.\n".to_string()
+    } else {
+        schedule_string
+    }
+}
+
+/// Ресурс (врач или кабинет ЛДП) по направлению, для которого запрашивается расписание.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduleTarget {
+    pub referral_id: u64,
+    pub resource_id: u64,
+    pub complex_resource_id: u64
+}
+
+pub async fn get_schedule_obj(user:&Model, target:&ScheduleTarget) -> Result<ScheduleInfoResponse, EmiasError> {
+    EMIAS.get().unwrap().get_schedule_info(
+        &oms::format(user.oms_card.unwrap()), 
+        &user.date_birth.unwrap(), 
+        target.resource_id, 
+        target.complex_resource_id, 
+        Some(target.referral_id)
+    ).await
+}
+
+pub async fn find_slot(user:&Model, target:&ScheduleTarget, start:&NaiveDateTime) -> Result<Option<Slot>, EmiasError> {
+    let schedule = get_schedule_obj(user, target).await?;
 
-            for referral in referrals.result {
-                message_string += &format!(
-                    "[{start} - {end}] {name}\n", 
-                    start = NaiveDate::parse_from_str(&referral.start_time, "%Y-%m-%d").unwrap().format("%d.%m.%Y").to_string(),
-                    end = NaiveDate::parse_from_str(&referral.end_time, "%Y-%m-%d").unwrap().format("%d.%m.%Y").to_string(),
-                    name = if referral.to_doctor.is_some() { referral.to_doctor.unwrap().speciality_name  } else { referral.to_ldp.unwrap().ldp_type_name },
-                );
+    Ok(find_slot_in(schedule.result, start))
+}
+
+pub fn find_slot_in(schedule: ScheduleInfo, start:&NaiveDateTime) -> Option<Slot> {
+    schedule.schedule_of_day
+        .into_iter()
+        .flat_map(|day| day.schedule_by_slot)
+        .flat_map(|by_slot| by_slot.slot)
+        .find(|slot| slot.start_time.format(SLOT_FORMAT).to_string() == start.format(SLOT_FORMAT).to_string())
+}
 
-                let doctors_string = get_doctors_with_shedule(user, &referral.id).await;
+pub async fn find_appointment(user:&Model, appointment_id:&u64) -> Result<Option<AppointmentInfo>, EmiasError> {
+    let appointments = EMIAS.get().unwrap().get_appointments_info(&oms::format(user.oms_card.unwrap()), &user.date_birth.unwrap()).await?;
 
-                match doctors_string {
-                    Ok(doc_str) => {
-                        message_string += &doc_str;
-                    },
-                    Err(err) => return Err(err),
-                }
-            }
-            return Ok(message_string)
-        },
-        Err(e) => return Err(e)
+    Ok(appointments.result.appointment.into_iter().find(|a| a.id == *appointment_id))
+}
+
+pub async fn get_appointment_schedule_obj(user:&Model, appointment:&AppointmentInfo) -> Result<ScheduleInfoResponse, EmiasError> {
+    EMIAS.get().unwrap().get_schedule_info(
+        &oms::format(user.oms_card.unwrap()), 
+        &user.date_birth.unwrap(), 
+        appointment.available_resource_id, 
+        appointment.complex_resource_id, 
+        appointment.referral_id
+    ).await
+}
+
+pub fn collect_appointment_info(appointment: &AppointmentInfo) -> String {
+    let mut appointment_string = format!(
+        "[{}] {}\n",
+        appointment.start_time.format("%d.%m.%Y %H:%M"),
+        appointment.name()
+    );
+
+    let place = [
+        appointment.lpu_short_name.clone(),
+        appointment.room_number.as_ref().map(|n| format!("каб. {n}")),
+        appointment.lpu_address.clone()
+    ].into_iter().flatten().collect::<Vec<String>>();
+
+    if !place.is_empty() {
+        appointment_string.push_str(&format!("- {}\n", place.join(", ")));
     }
+
+    appointment_string
 }
 
-pub async fn get_doctors_with_shedule(user:&Model, referral_id:&u64) -> Result<String, reqwest::Error> {
-
-    let doc_res = get_doctors_obj(user, referral_id).await;
-
-    match doc_res {
-        Ok(doctors) => {
-            let mut doctors_string = String::new();
-
-            if let doctors::ResultType::LdpArray(result) = doctors.result {
-                for ldp in result {
-                    doctors_string.push_str(&format!("- {}: \n", ldp.name));
-                    let free_rooms = collect_free_rooms_data(ldp);
-                    doctors_string.push_str(&free_rooms);
-                }
-            } else if let doctors::ResultType::DocArray(result) = doctors.result {
-                for doctor in result {
-                    doctors_string.push_str(&format!("- {} {} {}: \n", doctor.main_doctor.first_name, doctor.main_doctor.second_name, doctor.main_doctor.last_name));
-                    let free_rooms = collect_free_rooms_data(doctor);
-                    doctors_string.push_str(&free_rooms);
-                }
-            }
-            if doctors_string == "" {
-                doctors_string += "- Нет врачей по данному направлению.\n";
-            }
-            doctors_string += "\n";
-
-            return Ok(doctors_string)
-        },
-        Err(err) => return Err(err)
-            //let _ = loop_bot.send_message(ChatId(user.chat_id), format!("Не удалось получить список направлений по причине: `{}`", err.status().unwrap())).await;
+pub fn collect_appointments_data(appointments: &[AppointmentInfo]) -> String {
+    if appointments.is_empty() {
+        return "У вас нет активных записей.\n".to_string();
     }
+
+    let mut appointments_string = "Ваши записи: \n".to_string();
+
+    for appointment in appointments {
+        appointments_string.push_str(&collect_appointment_info(appointment));
+    }
+
+    appointments_string
+}
+
+pub async fn find_room(user:&Model, referral_id:&u64, complex_resource_id:&u64) -> Result<Option<Room>, EmiasError> {
+    let doctors = EMIAS.get().unwrap().get_doctors_info(&oms::format(user.oms_card.unwrap()), &user.date_birth.unwrap(), *referral_id).await?;
+
+    let complex_resources = match doctors.result {
+        doctors::ResultType::LdpArray(result) => result.into_iter().flat_map(|ldp| ldp.complex_resource).collect(),
+        doctors::ResultType::DocArray(result) => result.into_iter().flat_map(|doctor| doctor.complex_resource).collect(),
+        doctors::ResultType::EmptyObject(_) => vec![]
+    };
+
+    Ok(complex_resources.into_iter().find(|c| c.id == *complex_resource_id).and_then(|c| c.room))
 }
 
-pub async fn get_referrals_obj(user: &Model) -> Result<ReferralsInfoResponse, reqwest::Error> {
-    let ref_data = BasicRequest::<ReferralsInfoParamsRequest>::new(
-        Some("123".to_owned()), 
-        user.oms_card.unwrap().to_string(), 
-        user.date_birth.unwrap().to_string()
+pub fn collect_appointment_data(slot: &Slot, room: Option<&Room>) -> String {
+    let mut appointment_string = format!(
+        "Дата: {}\nВремя: {} - {}\n",
+        slot.start_time.format("%d.%m.%Y"),
+        slot.start_time.format("%H:%M"),
+        slot.end_time.format("%H:%M")
     );
 
-    let ref_res = reqwest::Client::new()
-        .post("https://emias.info/api/emc/appointment-eip/v1/?getReferralsInfo")
-        .json(&ref_data)
-        .send()
-        .await;
-
-    match ref_res {
-        Ok(v) => {
-            Ok(v.json::<ReferralsInfoResponse>().await?)
-        },
-        Err(err) => Err(err)
+    if let Some(room) = room {
+        appointment_string.push_str(&format!(
+            "Кабинет: {}\nПоликлиника: {}\nАдрес: {}\n", 
+            room.number, room.lpu_short_name, room.default_address
+        ));
     }
+
+    appointment_string
 }
 
-pub async fn get_doctors_obj(user:&Model, referral_id:&u64) -> Result<DoctorsInfoParamsResponse,reqwest::Error> {
-    let doc_data = BasicRequest::<DoctorsInfoParamsRequest>::new(
-        Some("123".to_owned()),
-        user.oms_card.unwrap().to_string(),
-        user.date_birth.unwrap().to_string(),
-        *referral_id
-    );
+/// Формат даты и времени начала слота в данных inline-кнопок.
+pub const SLOT_FORMAT: &str = "%Y%m%d%H%M";
+
+/// Формат дня расписания в данных inline-кнопок.
+pub const DAY_FORMAT: &str = "%Y%m%d";
+
+pub fn referral_name(referral: &ReferralInfo) -> String {
+    match (&referral.to_doctor, &referral.to_ldp) {
+        (Some(to_doctor), _) => to_doctor.speciality_name.clone(),
+        (None, Some(to_ldp)) => to_ldp.ldp_type_name.clone(),
+        (None, None) => referral.lpu_name.clone()
+    }
+}
+
+/// Ближайшие свободные даты по направлению, сгруппированные по короткому названию поликлиники.
+pub type AvailabilitySnapshot = BTreeMap<String, BTreeSet<NaiveDate>>;
+
+/// Свободный кабинет (врач или ЛДП) по направлению с ближайшей датой записи. В таком виде
+/// кабинеты направления хранятся в снимке доступности (`availability`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FreeRoom {
+    pub resource_id: u64,
+    pub complex_resource_id: u64,
+    pub resource_name: String,
+    pub lpu_id: u32,
+    pub lpu_short_name: String,
+    pub date: NaiveDate
+}
+
+#[derive(Debug)]
+pub struct ReferralAvailability {
+    pub referral_id: u64,
+    pub name: String,
+    pub end_date: Option<NaiveDate>,
+    pub rooms: Vec<FreeRoom>
+}
 
-    let doc_res = reqwest::Client::new()
-        .post("https://emias.info/api/emc/appointment-eip/v1/?getDoctorsInfo")
-        .json(&doc_data)
-        .send()
-        .await;
+impl ReferralAvailability {
+    /// Снимок дат по кабинетам, прошедшим фильтр.
+    pub fn snapshot(&self, filter: impl Fn(u64, &FreeRoom) -> bool) -> AvailabilitySnapshot {
+        available_dates(self.referral_id, &self.rooms, filter)
+    }
+}
 
-    match doc_res {
-        Ok(v) => Ok(v.json::<DoctorsInfoParamsResponse>().await?),
-        Err(err) => Err(err)
+/// Ближайшие даты кабинетов направления, прошедших фильтр, по поликлиникам.
+pub fn available_dates(referral_id: u64, rooms: &[FreeRoom], filter: impl Fn(u64, &FreeRoom) -> bool) -> AvailabilitySnapshot {
+    let mut dates = AvailabilitySnapshot::new();
+    for room in rooms.iter().filter(|room| filter(referral_id, room)) {
+        dates.entry(room.lpu_short_name.clone()).or_default().insert(room.date);
     }
+    dates
+}
 
+/// Свободные кабинеты по всем направлениям пользователя. Врачи по направлениям
+/// запрашиваются параллельно, не больше `concurrency` запросов одновременно.
+pub async fn get_user_availability(user: &Model, concurrency: usize) -> Result<Vec<ReferralAvailability>, EmiasError> {
+    let referrals = EMIAS.get().unwrap().get_referrals_info(&oms::format(user.oms_card.unwrap()), &user.date_birth.unwrap()).await?;
+
+    stream::iter(referrals.result.into_iter().map(|referral| get_referral_availability(user, referral)))
+        .buffered(concurrency.max(1))
+        .try_collect()
+        .await
 }
 
-pub fn collect_free_rooms_data<T:HasComplexResource>(resource:T) -> String {
-    let complex_res = resource.complex_resource();
-    let mut rooms_string = String::new();
+async fn get_referral_availability(user: &Model, referral: ReferralInfo) -> Result<ReferralAvailability, EmiasError> {
+    let doctors = EMIAS.get().unwrap().get_doctors_info(&oms::format(user.oms_card.unwrap()), &user.date_birth.unwrap(), referral.id).await?;
+
+    let resources = match doctors.result {
+        doctors::ResultType::LdpArray(result) => result.into_iter()
+            .map(|ldp| (ldp.id, ldp.name, ldp.complex_resource))
+            .collect(),
+        doctors::ResultType::DocArray(result) => result.into_iter()
+            .map(|doctor| (
+                doctor.id, 
+                format!("{} {} {}", doctor.main_doctor.first_name, doctor.main_doctor.second_name, doctor.main_doctor.last_name), 
+                doctor.complex_resource
+            ))
+            .collect(),
+        doctors::ResultType::EmptyObject(_) => vec![]
+    };
 
-    for complex in complex_res {
-        match &complex.room {
-            Some(v) => {
-                rooms_string.push_str(
-                    &format!("[{}] \n", v.availability_date.format("%d.%m.%Y").to_string())
-                )
-            },
-            None => {
-                continue;
-            }
+    let rooms = resources.into_iter()
+        .flat_map(|(resource_id, resource_name, complex_resource)| {
+            complex_resource.into_iter().filter_map(|c| c.room.map(|room| (c.id, room))).map(move |(complex_resource_id, room)| FreeRoom {
+                resource_id,
+                complex_resource_id,
+                resource_name: resource_name.clone(),
+                lpu_id: room.lpu_id,
+                lpu_short_name: room.lpu_short_name,
+                date: room.availability_date
+            })
+        })
+        .collect();
+
+    Ok(ReferralAvailability {
+        referral_id: referral.id,
+        name: referral_name(&referral),
+        end_date: NaiveDate::parse_from_str(&referral.end_time, "%Y-%m-%d").ok(),
+        rooms
+    })
+}
+
+/// Строки вида «+2 новые даты в ГП № 2: 20.09.2024, 21.09.2024» для всех изменений
+/// между прошлым и текущим снимком свободных дат.
+pub fn collect_availability_changes(old: &AvailabilitySnapshot, new: &AvailabilitySnapshot) -> Vec<String> {
+    let empty = BTreeSet::new();
+    let lpus = old.keys().chain(new.keys()).collect::<BTreeSet<&String>>();
+    let mut changes = vec![];
+
+    for lpu in lpus {
+        let old_dates = old.get(lpu).unwrap_or(&empty);
+        let new_dates = new.get(lpu).unwrap_or(&empty);
+
+        let added = new_dates.difference(old_dates).collect::<Vec<&NaiveDate>>();
+        let removed = old_dates.difference(new_dates).collect::<Vec<&NaiveDate>>();
+
+        if !added.is_empty() {
+            let word = match plural_form(added.len()) {
+                PluralForm::One => "новая дата",
+                PluralForm::Few => "новые даты",
+                PluralForm::Many => "новых дат"
+            };
+            changes.push(format!("+{} {} в {}: {}", added.len(), word, lpu, join_dates(&added)));
+        }
+        if !removed.is_empty() {
+            let word = match plural_form(removed.len()) {
+                PluralForm::One => "дата больше недоступна",
+                PluralForm::Few => "даты больше недоступны",
+                PluralForm::Many => "дат больше недоступны"
+            };
+            changes.push(format!("-{} {} в {}: {}", removed.len(), word, lpu, join_dates(&removed)));
         }
     }
 
-    if rooms_string.len() == 0 {
-        "Нет записей.\n".to_string()
-    } else {
-        rooms_string += "\n";
-        rooms_string
+    changes
+}
+
+fn join_dates(dates: &[&NaiveDate]) -> String {
+    dates.iter().map(|d| d.format("%d.%m.%Y").to_string()).collect::<Vec<String>>().join(", ")
+}
+
+pub enum PluralForm {
+    One,
+    Few,
+    Many
+}
+
+pub fn plural_form(n: usize) -> PluralForm {
+    match (n % 10, n % 100) {
+        (1, rem) if rem != 11 => PluralForm::One,
+        (2..=4, rem) if !(12..=14).contains(&rem) => PluralForm::Few,
+        _ => PluralForm::Many
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,16 @@
+//! Клиентская часть бота для работы с API ЕМИАС: типы запросов и ответов, HTTP-клиент
+//! и мок-сервер (фича `mock`). Вынесена в библиотеку, чтобы её можно было проверять
+//! интеграционными тестами без Telegram и базы данных.
+
+pub mod parsable;
+
+pub mod error;
+
+pub mod limiter;
+
+pub mod cache;
+
+pub mod emias;
+
+#[cfg(feature = "mock")]
+pub mod mock;
@@ -0,0 +1,64 @@
+use std::{sync::Arc, time::Duration};
+
+use rand::Rng;
+use tokio::{sync::{Mutex, OwnedSemaphorePermit, Semaphore}, time::Instant};
+
+/// Общий на всё приложение ограничитель запросов к ЕМИАС: не больше `requests_per_second`
+/// запросов в секунду и не больше `max_concurrency` запросов одновременно.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next_slot: Mutex<Instant>,
+    concurrency: Arc<Semaphore>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64, max_concurrency: usize) -> Self {
+        let interval = if requests_per_second > 0.0 {
+            Duration::from_secs_f64(1.0 / requests_per_second)
+        } else {
+            Duration::ZERO
+        };
+
+        Self {
+            interval,
+            next_slot: Mutex::new(Instant::now()),
+            concurrency: Arc::new(Semaphore::new(max_concurrency.max(1))),
+        }
+    }
+
+    /// Ждёт свободного места и очереди по частоте. Запрос можно отправлять, пока жив
+    /// возвращённый permit.
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        let permit = self.concurrency.clone().acquire_owned().await.expect("semaphore is never closed");
+
+        let start_at = {
+            let mut next_slot = self.next_slot.lock().await;
+            let start_at = (*next_slot).max(Instant::now());
+            *next_slot = start_at + self.interval;
+            start_at
+        };
+        tokio::time::sleep_until(start_at).await;
+
+        permit
+    }
+}
+
+/// Параметры повторов при временных сбоях ЕМИАС.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Задержка перед повтором номер `attempt` (с нуля): экспоненциальный рост
+    /// с «полным» джиттером, чтобы одновременно упавшие запросы не повторялись залпом.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exp = self.base_delay.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_delay);
+        let jittered = rand::thread_rng().gen_range(0.0..=1.0) * exp.as_secs_f64();
+
+        Duration::from_secs_f64(jittered)
+    }
+}
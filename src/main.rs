@@ -1,20 +1,52 @@
 use dotenv::dotenv;
-use em_commands::callback::{back_to_main, get_doctors, get_referrals};
+use em_commands::callback::{
+    back_to_main, book_slot, cancel_appointment, confirm_cancel, confirm_shift, confirm_slot, get_appointments, get_doctors, 
+    get_referrals, get_schedule, get_slots, shift_appointment, shift_days, shift_slots, delete_watch_rule, watch_resource, 
+    get_profiles, use_profile, confirm_remove_profile, remove_profile_ok, verify_profile_again, get_watch_rules
+};
 use std::{env, error::Error};
-use teloxide::{dispatching::dialogue::GetChatId, prelude::*, types::{InlineKeyboardButton, InlineKeyboardMarkup, Me}, utils::command::BotCommands};
-use sea_orm::{ColumnTrait, ConnectOptions, Database, DatabaseConnection, EntityTrait, QueryFilter};
+use teloxide::{dispatching::dialogue::{GetChatId, InMemStorage}, prelude::*, types::Me, utils::command::BotCommands};
+use sea_orm::{ConnectOptions, Database, DatabaseConnection};
 
 pub mod entities;
-use entities::{info, prelude::*};
 
-pub mod parsable;
+pub use em_bot::{cache, emias, error, limiter, parsable};
 
 pub mod helper;
-use helper::get_user_referrals;
+
+pub mod message_builder;
 
 pub mod em_commands;
+use em_commands::onboarding::{self, Onboarding, OnboardingDialogue};
+use em_commands::callback_data::{CallbackAction, CallbackData};
+use em_commands::callback_session;
+
+pub mod poller;
+
+pub mod watch;
+
+pub mod profiles;
+
+pub mod auto_book;
+
+pub mod reminders;
+
+pub mod appointment_reminders;
+
+pub mod oms;
+
+pub mod verification;
+
+#[cfg(test)]
+mod test_support;
+
+use emias::EmiasClient;
+
+/// Сколько ждать завершения начатых проверок при остановке бота.
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
 
 pub static DB:tokio::sync::OnceCell<DatabaseConnection> = tokio::sync::OnceCell::const_new();
+pub static EMIAS:tokio::sync::OnceCell<EmiasClient> = tokio::sync::OnceCell::const_new();
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -30,57 +62,52 @@ async fn main() -> Result<(), Box<dyn Error>> {
         Database::connect(opt).await.unwrap()
     }).await;
 
+    EMIAS.set(EmiasClient::from_env().expect("Не удалось создать HTTP-клиент ЕМИАС.")).unwrap();
+
     let bot = Bot::new(token);
-    let loop_bot = bot.clone();
-
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(60*30));
-
-        loop {
-            interval.tick().await;
-
-            let users_to_send = Info::find()
-                .filter(info::Column::OmsCard.is_not_null())
-                .filter(info::Column::DateBirth.is_not_null())
-                .all(DB.get().unwrap())
-                .await.expect("Не могу прочитать БАЗУ.");
-
-            for user in users_to_send {
-                let message = get_user_referrals(&user).await;
-
-                match message {
-                    Ok(message) => {
-                        let go_to_ref_button = InlineKeyboardButton::new(
-                            "Записаться", 
-                            teloxide::types::InlineKeyboardButtonKind::CallbackData("get_referrals".to_string())
-                        );
-                        let markup = InlineKeyboardMarkup::new([[go_to_ref_button]]);
-
-                        let _ = loop_bot.send_message(
-                            ChatId(user.chat_id), 
-                            message
-                        ).reply_markup(markup).await;
-                    },
-                    Err(err) => {
-                        let _ = loop_bot.send_message(
-                            ChatId(user.chat_id), 
-                            format!(
-                                "Не удалось получить список направлений: \n-Ошибка в запросе по ссылке: `{}`; \n-Код ответа: `{}`.", 
-                                err.url().unwrap(), 
-                                err.status().unwrap()
-                            )
-                        ).await;
-                    }
-                }
-            }
-        }
-    });
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let poller = tokio::spawn(poller::run(bot.clone(), shutdown_rx.clone()));
+    let reminders = tokio::spawn(appointment_reminders::run(bot.clone(), shutdown_rx));
 
     let handler = dptree::entry()
-        .branch(Update::filter_message().endpoint(message_handler))
-        .branch(Update::filter_callback_query().endpoint(callback_handler));
+        .branch(
+            Update::filter_message()
+                .enter_dialogue::<Message, InMemStorage<Onboarding>, Onboarding>()
+                .branch(
+                    dptree::filter(|msg: Message, state: Onboarding| !matches!(state, Onboarding::Idle) && is_cancel(&msg))
+                        .endpoint(onboarding::cancel)
+                )
+                .branch(dptree::case![Onboarding::ReceiveOms { profile_id }].endpoint(onboarding::receive_oms))
+                .branch(dptree::case![Onboarding::ReceiveBirthDate { profile_id, oms }].endpoint(onboarding::receive_birth_date))
+                .branch(dptree::case![Onboarding::Confirm { profile_id, oms, birth_date }].endpoint(onboarding::waiting_confirm))
+                .endpoint(message_handler)
+        )
+        .branch(
+            Update::filter_callback_query()
+                .filter_map_async(resolve_callback)
+                .enter_dialogue::<CallbackQuery, InMemStorage<Onboarding>, Onboarding>()
+                .branch(dptree::filter(|data: CallbackData| data.action.is_onboarding()).endpoint(onboarding::callback))
+                .endpoint(callback_handler)
+        );
 
-    Dispatcher::builder(bot.clone(), handler).enable_ctrlc_handler().build().dispatch().await;
+    Dispatcher::builder(bot.clone(), handler)
+        .dependencies(dptree::deps![InMemStorage::<Onboarding>::new()])
+        .enable_ctrlc_handler()
+        .build()
+        .dispatch()
+        .await;
+
+    // Диспетчер остановлен по Ctrl-C: даём фоновым задачам закончить начатую работу.
+    log::info!("Stopping background tasks...");
+    let _ = shutdown_tx.send(true);
+    let stopped = tokio::time::timeout(SHUTDOWN_TIMEOUT, async {
+        let _ = poller.await;
+        let _ = reminders.await;
+    }).await;
+    if stopped.is_err() {
+        log::warn!("Background tasks did not stop in {}s, exiting anyway", SHUTDOWN_TIMEOUT.as_secs());
+    }
 
     Ok(())
 }
@@ -90,57 +117,192 @@ async fn main() -> Result<(), Box<dyn Error>> {
 enum EmCommand {
     #[command(description = "показать этот текст.")]
     Help,
-    #[command(description = "инициализировать вашу запись в боте.")]
+    #[command(description = "инициализировать вашу запись в боте: бот спросит полис ОМС и дату рождения.")]
     Start,    
+    #[command(description = "отменить ввод данных.")]
+    Cancel,
     #[command(description = "изменить номер ПОЛИСа.")]
     OmsCard(String),
     #[command(description = "изменить дату рождения (в формате DD.MM.YYYY).")]
     DateBirth(String),
     #[command(description = "показать актуальную инфомрацию обо мне в системе.")]
-    Info
+    Info,
+    #[command(description = "показать мои записи к врачам.")]
+    Appointments,
+    #[command(description = "правила отслеживания слотов: без аргументов — список, с условиями — новое правило.")]
+    Watch(String),
+    #[command(description = "показать профили пациентов, переключить или удалить профиль.")]
+    Profiles,
+    #[command(description = "добавить профиль пациента, например `/addprofile Мама`.")]
+    AddProfile(String)
 }
 
-async fn callback_handler(bot: Bot, callback: CallbackQuery ) -> Result<(), Box<dyn Error + Send + Sync>> {
+/// Разбирает данные нажатой кнопки. На неизвестные, устаревшие и истёкшие кнопки сразу
+/// отвечает всплывающим пояснением, и дальше такое нажатие не обрабатывается.
+async fn resolve_callback(bot: Bot, callback: CallbackQuery) -> Option<CallbackData> {
+    match callback_session::resolve(callback.data.as_deref().unwrap_or_default()).await {
+        Ok(data) => Some(data),
+        Err(err) => {
+            log::info!("Rejected button press: {}", err);
+            if let Err(err) = bot.answer_callback_query(&callback.id).text(err.user_message()).show_alert(true).await {
+                log::error!("Failed to answer callback query: {}", err);
+            }
+            None
+        }
+    }
+}
 
-    let chat_id = callback.chat_id().unwrap();
-    let message_id = callback.message.unwrap().id();
-    //let chat_id = callback.chat_id().unwrap();
-    if let Some(command) = callback.data {
-        let user = Info::find().filter(info::Column::ChatId.eq(callback.from.id.0)).one(DB.get().unwrap()).await.unwrap().unwrap();
+async fn callback_handler(bot: Bot, callback: CallbackQuery, data: CallbackData) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Сообщение с кнопкой могло стать недоступным (слишком старое) — редактировать нечего.
+    let (Some(chat_id), Some(message_id)) = (callback.chat_id(), callback.message.as_ref().map(|message| message.id())) else {
+        bot.answer_callback_query(&callback.id).text("Это меню устарело. Откройте его заново.").show_alert(true).await?;
+        return Ok(());
+    };
+    bot.answer_callback_query(&callback.id).await?;
 
-        let command_parts = command.split("/").collect::<Vec<&str>>();
+    match data.action {
+        CallbackAction::Noop => return Ok(()),
+        CallbackAction::Profiles { page } => {
+            get_profiles(bot, chat_id, message_id, page).await;
+            return Ok(());
+        },
+        CallbackAction::ProfileUse { profile_id } => {
+            use_profile(bot, chat_id, message_id, &profile_id).await;
+            return Ok(());
+        },
+        CallbackAction::ProfileDelete { profile_id } => {
+            confirm_remove_profile(bot, chat_id, message_id, &profile_id).await;
+            return Ok(());
+        },
+        CallbackAction::ProfileDeleteOk { profile_id } => {
+            remove_profile_ok(bot, chat_id, message_id, &profile_id).await;
+            return Ok(());
+        },
+        CallbackAction::ProfileVerify { profile_id } => {
+            verify_profile_again(bot, chat_id, &profile_id).await;
+            return Ok(());
+        },
+        _ => {}
+    }
 
-        match command_parts[0] {
-            "get_referrals" => {
-                get_referrals(bot, user, chat_id, message_id).await;
-            },
-            "back_to_main" => {
-                back_to_main(bot, chat_id, message_id).await;
-            },
-            "get_doctors" => {
-                let referral_id = command_parts[1].parse().unwrap();
-                get_doctors(bot, user, chat_id, message_id, &referral_id).await;
-            }
-            _ => {
+    // Кнопки уведомлений помечены профилем: нажатие делает этот профиль активным, чтобы и
+    // следующие кнопки сценария работали с ним.
+    let user = match data.profile_id {
+        Some(profile_id) => match profiles::chat_profile(chat_id.0, profile_id).await? {
+            Some(profile) if !profile.is_active => profiles::switch_profile(chat_id.0, profile.id).await?,
+            profile => profile
+        },
+        None => profiles::active_profile(chat_id.0).await?
+    };
+    let Some(user) = user else {
+        bot.send_message(chat_id, "Профиль не найден. Используйте `/start` или `/profiles`.").await?;
+        return Ok(());
+    };
+    // Профиль мог остаться незаполненным, например если знакомство с ботом прервали.
+    if data.action.needs_patient() && (user.oms_card.is_none() || user.date_birth.is_none()) {
+        bot.send_message(chat_id, format!("Профиль «{}» не заполнен: укажите полис ОМС и дату рождения командой `/start`.", profiles::display_name(&user))).await?;
+        return Ok(());
+    }
 
-            }
-        }
+    match data.action {
+        CallbackAction::Referrals { page } => {
+            get_referrals(bot, user, chat_id, message_id, page).await;
+        },
+        CallbackAction::RefreshReferrals => {
+            EMIAS.get().unwrap().invalidate(&oms::format(user.oms_card.unwrap()), &user.date_birth.unwrap());
+            get_referrals(bot, user, chat_id, message_id, 0).await;
+        },
+        CallbackAction::RefreshDoctors { referral_id } => {
+            EMIAS.get().unwrap().invalidate(&oms::format(user.oms_card.unwrap()), &user.date_birth.unwrap());
+            get_doctors(bot, user, chat_id, message_id, &referral_id, 0).await;
+        },
+        CallbackAction::MainMenu => {
+            back_to_main(bot, chat_id, message_id).await;
+        },
+        CallbackAction::Doctors { referral_id, page } => {
+            get_doctors(bot, user, chat_id, message_id, &referral_id, page).await;
+        },
+        CallbackAction::Schedule { target, page } => {
+            get_schedule(bot, user, chat_id, message_id, &target, page).await;
+        },
+        CallbackAction::Slots { target, date, page } => {
+            get_slots(bot, user, chat_id, message_id, &target, &date, page).await;
+        },
+        CallbackAction::ConfirmSlot { target, start } => {
+            confirm_slot(bot, user, chat_id, message_id, &target, &start).await;
+        },
+        CallbackAction::BookSlot { target, start } => {
+            book_slot(bot, user, chat_id, message_id, &target, &start).await;
+        },
+        CallbackAction::Appointments { page } => {
+            get_appointments(bot, user, chat_id, message_id, page).await;
+        },
+        CallbackAction::CancelAppointment { appointment_id } => {
+            confirm_cancel(bot, user, chat_id, message_id, &appointment_id).await;
+        },
+        CallbackAction::CancelAppointmentOk { appointment_id } => {
+            cancel_appointment(bot, user, chat_id, message_id, &appointment_id).await;
+        },
+        CallbackAction::ShiftDays { appointment_id, page } => {
+            shift_days(bot, user, chat_id, message_id, &appointment_id, page).await;
+        },
+        CallbackAction::ShiftSlots { appointment_id, date, page } => {
+            shift_slots(bot, user, chat_id, message_id, &appointment_id, &date, page).await;
+        },
+        CallbackAction::ShiftConfirm { appointment_id, start } => {
+            confirm_shift(bot, user, chat_id, message_id, &appointment_id, &start).await;
+        },
+        CallbackAction::ShiftOk { appointment_id, start } => {
+            shift_appointment(bot, user, chat_id, message_id, &appointment_id, &start).await;
+        },
+        CallbackAction::WatchResource { referral_id, resource_id } => {
+            watch_resource(bot, user, chat_id, &referral_id, &resource_id).await;
+        },
+        CallbackAction::WatchRules { page } => {
+            get_watch_rules(bot, user, chat_id, message_id, page).await;
+        },
+        CallbackAction::Unwatch { rule_id } => {
+            delete_watch_rule(bot, user, chat_id, message_id, &rule_id).await;
+        },
+        // Кнопки знакомства с ботом вне диалога: ввод данных уже завершён или отменён.
+        CallbackAction::OnboardingCancel | CallbackAction::OnboardingRestart | CallbackAction::OnboardingSave => {
+            bot.send_message(chat_id, "Эта кнопка уже неактуальна. Начать заново можно командой `/start`.").await?;
+        },
+        CallbackAction::Noop
+        | CallbackAction::Profiles { .. }
+        | CallbackAction::ProfileUse { .. }
+        | CallbackAction::ProfileDelete { .. }
+        | CallbackAction::ProfileDeleteOk { .. }
+        | CallbackAction::ProfileVerify { .. } => {}
     }
-    
 
     Ok(())
 }
 
-async fn message_handler(bot: Bot, msg: Message, me: Me) -> Result<(), Box<dyn Error + Send + Sync>> {
+/// `/cancel` или «отмена» во время ввода данных.
+fn is_cancel(msg: &Message) -> bool {
+    msg.text().is_some_and(|text| {
+        let text = text.trim().to_lowercase();
+        text == "отмена" || text == "/cancel" || text.starts_with("/cancel@")
+    })
+}
+
+async fn message_handler(bot: Bot, msg: Message, me: Me, dialogue: OnboardingDialogue) -> Result<(), Box<dyn Error + Send + Sync>> {
     if let Some(text) = msg.text() {
-        let cmd = BotCommands::parse(text, me.username()).unwrap();
+        let Ok(cmd) = BotCommands::parse(text, me.username()) else {
+            bot.send_message(msg.chat.id, "Не понимаю эту команду. Список команд — `/help`.").await?;
+            return Ok(());
+        };
 
         match cmd {
             EmCommand::Help => {
                 em_commands::message::help(bot, msg).await;
             },
             EmCommand::Start => {
-                em_commands::message::start(bot, msg).await;
+                em_commands::message::start(bot, msg, dialogue).await;
+            },
+            EmCommand::Cancel => {
+                bot.send_message(msg.chat.id, "Сейчас нечего отменять.").await?;
             },
             EmCommand::OmsCard(oms) => {
                 em_commands::message::oms_card(bot, msg, oms).await;
@@ -150,6 +312,18 @@ async fn message_handler(bot: Bot, msg: Message, me: Me) -> Result<(), Box<dyn E
             },
             EmCommand::Info => {
                 em_commands::message::info(bot, msg).await;
+            },
+            EmCommand::Appointments => {
+                em_commands::message::appointments(bot, msg).await;
+            },
+            EmCommand::Watch(args) => {
+                em_commands::message::watch(bot, msg, args).await;
+            },
+            EmCommand::Profiles => {
+                em_commands::message::profiles(bot, msg).await;
+            },
+            EmCommand::AddProfile(name) => {
+                em_commands::message::add_profile_cmd(bot, msg, dialogue, name).await;
             }
         };
     }
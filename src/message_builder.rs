@@ -0,0 +1,195 @@
+use teloxide::{prelude::*, types::{InlineKeyboardMarkup, MessageId}, RequestError};
+
+/// Максимальная длина текста сообщения Telegram (в UTF-16 символах).
+pub const MESSAGE_LIMIT: usize = 4096;
+
+/// Собирает длинный текст из блоков (направление, день расписания) и делит его на сообщения не длиннее
+/// лимита Telegram. Блоки по возможности не разрываются: новое сообщение начинается на
+/// границе блока, а строки режутся только если один блок сам длиннее лимита.
+#[derive(Debug)]
+pub struct MessageBuilder {
+    limit: usize,
+    parts: Vec<String>,
+    current: String,
+}
+
+impl Default for MessageBuilder {
+    fn default() -> Self {
+        Self::with_limit(MESSAGE_LIMIT)
+    }
+}
+
+impl MessageBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limit(limit: usize) -> Self {
+        Self { limit: limit.max(1), parts: vec![], current: String::new() }
+    }
+
+    pub fn push(&mut self, block: impl AsRef<str>) -> &mut Self {
+        let block = block.as_ref();
+
+        if text_len(&self.current) + text_len(block) <= self.limit {
+            self.current.push_str(block);
+        } else if text_len(block) <= self.limit {
+            self.flush();
+            self.current.push_str(block);
+        } else {
+            for line in block.split_inclusive('\n') {
+                self.push_line(line);
+            }
+        }
+
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty() && self.current.is_empty()
+    }
+
+    pub fn build(mut self) -> Vec<String> {
+        self.flush();
+        self.parts
+    }
+
+    fn push_line(&mut self, line: &str) {
+        if text_len(&self.current) + text_len(line) > self.limit {
+            self.flush();
+        }
+
+        let mut rest = line;
+        while text_len(rest) > self.limit {
+            let split = split_index(rest, self.limit);
+            self.current.push_str(&rest[..split]);
+            self.flush();
+            rest = &rest[split..];
+        }
+        self.current.push_str(rest);
+    }
+
+    fn flush(&mut self) {
+        if !self.current.is_empty() {
+            self.parts.push(std::mem::take(&mut self.current));
+        }
+    }
+}
+
+fn text_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Байтовый индекс, до которого в строке помещается не больше `limit` UTF-16 символов.
+fn split_index(text: &str, limit: usize) -> usize {
+    let mut len = 0;
+    for (index, ch) in text.char_indices() {
+        len += ch.len_utf16();
+        if len > limit {
+            return index;
+        }
+    }
+    text.len()
+}
+
+/// Отправляет части сообщения по порядку; клавиатура прикрепляется к последней части.
+/// Ошибка отправки пишется в лог и возвращается вызывающему, остальные части не отправляются.
+pub async fn send_parts(bot: &Bot, chat_id: ChatId, parts: Vec<String>, markup: Option<InlineKeyboardMarkup>) -> Result<(), RequestError> {
+    let count = parts.len();
+
+    for (index, part) in parts.into_iter().enumerate() {
+        let request = bot.send_message(chat_id, part);
+        let sent = match (&markup, index + 1 == count) {
+            (Some(markup), true) => request.reply_markup(markup.clone()).await,
+            _ => request.await
+        };
+
+        if let Err(err) = sent {
+            log::error!("Failed to send part {}/{} to chat {}: {}", index + 1, count, chat_id, err);
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+/// Показывает части сообщения на месте экрана `message_id`: первая часть заменяет его
+/// текст, остальные отправляются следом, а клавиатура прикрепляется к последней части.
+pub async fn edit_parts(bot: &Bot, chat_id: ChatId, message_id: MessageId, mut parts: Vec<String>, markup: InlineKeyboardMarkup) -> Result<(), RequestError> {
+    if parts.is_empty() {
+        return Ok(());
+    }
+    let first = parts.remove(0);
+
+    let request = bot.edit_message_text(chat_id, message_id, first);
+    let edited = if parts.is_empty() {
+        request.reply_markup(markup.clone()).await
+    } else {
+        request.await
+    };
+    if let Err(err) = edited {
+        log::error!("Failed to edit message {} in chat {}: {}", message_id, chat_id, err);
+        return Err(err);
+    }
+
+    send_parts(bot, chat_id, parts, Some(markup)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{block_on, bot, sent_texts};
+
+    #[test]
+    fn blocks_are_not_split_across_messages() {
+        let mut message = MessageBuilder::with_limit(10);
+        message.push("aaaa\n").push("bbbb\n").push("cccc\n");
+
+        assert_eq!(message.build(), ["aaaa\nbbbb\n", "cccc\n"]);
+    }
+
+    #[test]
+    fn oversized_block_is_split_by_lines() {
+        let mut message = MessageBuilder::with_limit(10);
+        message.push("head\n").push("aaaa\nbbbb\ncccc\n");
+
+        assert_eq!(message.build(), ["head\naaaa\n", "bbbb\ncccc\n"]);
+    }
+
+    #[test]
+    fn oversized_line_is_cut_at_limit() {
+        let mut message = MessageBuilder::with_limit(4);
+        message.push("abcdefghij");
+
+        assert_eq!(message.build(), ["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn length_is_counted_in_utf16() {
+        // Эмодзи занимает два UTF-16 символа, кириллица — по одному.
+        let mut message = MessageBuilder::with_limit(4);
+        message.push("😀😀😀");
+        assert_eq!(message.build(), ["😀😀", "😀"]);
+
+        let mut message = MessageBuilder::with_limit(4);
+        message.push("абвг").push("д");
+        assert_eq!(message.build(), ["абвг", "д"]);
+    }
+
+    #[test]
+    fn empty_builder_has_no_parts() {
+        let message = MessageBuilder::new();
+        assert!(message.is_empty());
+        assert!(message.build().is_empty());
+    }
+
+    #[test]
+    fn long_screen_is_continued_in_new_messages() {
+        block_on(async {
+            let parts = vec!["первая".to_string(), "вторая".to_string(), "третья".to_string()];
+            edit_parts(&bot(), ChatId(301), MessageId(1), parts.clone(), InlineKeyboardMarkup::default()).await.unwrap();
+
+            assert_eq!(sent_texts(301), parts);
+        });
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+/// Сценарий мок-сервера ЕМИАС: для каждого JSON-RPC метода — список возможных ответов.
+///
+/// ```json
+/// {
+///     "getReferralsInfo": [ { "result": [] } ],
+///     "getDoctorsInfo": [
+///         { "match": { "referralId": 1 }, "result": [] },
+///         { "match": { "referralId": 2 }, "error": { "code": 404, "message": "Направление не найдено" } }
+///     ],
+///     "getAvailableResourceScheduleInfo": [
+///         { "result": { "id": 1, "lpuId": 1, "scheduleOfDay": [] } },
+///         { "fromCall": 3, "result": { "id": 1, "lpuId": 1, "scheduleOfDay": [ ... ] } }
+///     ]
+/// }
+/// ```
+///
+/// Из записей, у которых совпали все параметры из `match` и `fromCall` не больше номера
+/// текущего вызова метода (с нуля), выбирается запись с наибольшим `fromCall`. Так можно
+/// описать слоты, которые появляются или пропадают со временем.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Scenario(pub HashMap<String, Vec<ScenarioEntry>>);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScenarioEntry {
+    #[serde(default, rename = "match")]
+    pub matches: Map<String, Value>,
+    #[serde(default)]
+    pub from_call: u32,
+    pub result: Option<Value>,
+    pub error: Option<Value>,
+    /// HTTP-статус ответа, по умолчанию 200.
+    pub status: Option<u16>,
+    /// `id` ответа, по умолчанию — `id` запроса.
+    pub id: Option<Value>,
+}
+
+impl Scenario {
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn respond(&self, method: &str, params: &Value, call: u32) -> (u16, Value) {
+        let entry = self.0.get(method)
+            .into_iter()
+            .flatten()
+            .filter(|entry| entry.from_call <= call)
+            .filter(|entry| entry.matches.iter().all(|(key, value)| params.get(key) == Some(value)))
+            .fold(None, |best: Option<&ScenarioEntry>, entry| match best {
+                Some(best) if best.from_call >= entry.from_call => Some(best),
+                _ => Some(entry)
+            });
+
+        match entry {
+            Some(entry) => {
+                let mut body = match &entry.error {
+                    Some(error) => json!({ "error": error }),
+                    None => json!({ "result": entry.result.clone().unwrap_or(Value::Null) })
+                };
+                if let Some(id) = &entry.id {
+                    body["id"] = id.clone();
+                }
+                (entry.status.unwrap_or(200), body)
+            },
+            None => (200, json!({ "error": { "code": -32601, "message": format!("Method not found: {}", method) } }))
+        }
+    }
+}
+
+/// Мок-сервер JSON-RPC API ЕМИАС для тестов и локальной разработки.
+///
+/// Понимает тот же формат запросов, что и [`crate::emias::EmiasClient`], поэтому
+/// достаточно указать клиенту `EMIAS_URL` вида `http://127.0.0.1:8089/`.
+pub struct MockEmias {
+    addr: SocketAddr,
+    calls: Arc<Mutex<HashMap<String, u32>>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockEmias {
+    pub async fn start(scenario: Scenario) -> io::Result<Self> {
+        Self::bind("127.0.0.1:0", scenario).await
+    }
+
+    pub async fn bind(addr: &str, scenario: Scenario) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let calls = Arc::new(Mutex::new(HashMap::new()));
+        let scenario = Arc::new(scenario);
+
+        let server_calls = calls.clone();
+        let handle = tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    continue;
+                };
+
+                let scenario = scenario.clone();
+                let calls = server_calls.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = handle_connection(stream, &scenario, &calls).await {
+                        log::warn!("mock EMIAS connection failed: {}", err);
+                    }
+                });
+            }
+        });
+
+        Ok(Self { addr, calls, handle })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Базовый URL для `EmiasConfig::base_url`.
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    /// Сколько раз был вызван метод.
+    pub fn calls(&self, method: &str) -> u32 {
+        self.calls.lock().unwrap().get(method).copied().unwrap_or(0)
+    }
+
+    /// Ждёт остановки сервера (для бинарника `mock_emias`, который работает до Ctrl-C).
+    pub async fn serve_forever(mut self) {
+        let _ = (&mut self.handle).await;
+    }
+}
+
+impl Drop for MockEmias {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, scenario: &Scenario, calls: &Mutex<HashMap<String, u32>>) -> io::Result<()> {
+    let body = read_request_body(&mut stream).await?;
+
+    let (status, response) = match serde_json::from_slice::<Value>(&body) {
+        Ok(request) => {
+            let method = request.get("method").and_then(Value::as_str).unwrap_or_default().to_owned();
+            let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+            let call = {
+                let mut calls = calls.lock().unwrap();
+                let counter = calls.entry(method.clone()).or_insert(0);
+                *counter += 1;
+                *counter - 1
+            };
+
+            let (status, mut response) = scenario.respond(&method, &params, call);
+            response["jsonrpc"] = json!("2.0");
+            if response.get("id").is_none() {
+                response["id"] = request.get("id").cloned().unwrap_or(Value::Null);
+            }
+            (status, response)
+        },
+        Err(err) => (400, json!({ "jsonrpc": "2.0", "id": null, "error": { "code": -32700, "message": err.to_string() } }))
+    };
+
+    let payload = response.to_string();
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason_phrase(status),
+        payload.len()
+    );
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(payload.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+async fn read_request_body(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before headers"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+
+        if let Some(pos) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let headers = String::from_utf8_lossy(&buffer[..header_end]);
+    let content_length = headers.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+
+    let mut body = buffer.split_off(header_end);
+    while body.len() < content_length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+
+    Ok(body)
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Unknown"
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+
+/// Длина единого номера полиса (ЕНП).
+pub const ENP_LEN: usize = 16;
+/// Длина номера временного свидетельства.
+const TEMPORARY_LEN: usize = 9;
+
+/// Почему номер полиса ОМС не подходит. `Display` — пояснение для пользователя бота.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OmsError {
+    Empty,
+    /// Символ, которого не бывает в номере полиса.
+    InvalidChar(char),
+    /// Номер временного свидетельства (9 цифр).
+    TemporaryCertificate,
+    /// Полис старого образца: серия с буквами или серия и номер на 10–14 цифр.
+    OldFormat,
+    /// Цифр не 16, и на другие известные форматы номер не похож.
+    WrongLength(usize),
+    /// Контрольная (последняя) цифра ЕНП не сходится — скорее всего, опечатка.
+    CheckDigit { expected: u8, actual: u8 },
+}
+
+impl fmt::Display for OmsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OmsError::Empty => write!(f, "Номер полиса не указан."),
+            OmsError::InvalidChar(c) => write!(f, "В номере полиса встретился символ «{}». Укажите только цифры, пробелы и дефисы допустимы.", c),
+            OmsError::TemporaryCertificate => write!(
+                f,
+                "Похоже, это номер временного свидетельства (9 цифр). ЕМИАС принимает только единый номер полиса из 16 цифр — он напечатан в самом свидетельстве."
+            ),
+            OmsError::OldFormat => write!(
+                f,
+                "Похоже, это полис старого образца (серия и номер). ЕМИАС принимает только единый номер полиса из 16 цифр — его можно узнать в страховой компании или на Госуслугах."
+            ),
+            OmsError::WrongLength(len) => write!(f, "В номере полиса {} цифр, а должно быть {}. Проверьте, не пропущена ли цифра.", len, ENP_LEN),
+            OmsError::CheckDigit { expected, actual } => write!(
+                f,
+                "Номер полиса не проходит проверку: последняя цифра {}, а по остальным цифрам должна быть {}. Скорее всего, в номере опечатка.",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OmsError {}
+
+/// Убирает пробелы и дефисы, которыми номер часто разбивают на группы при копировании.
+pub fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '-' | '–' | '—'))
+        .collect()
+}
+
+/// Контрольная цифра ЕНП по первым 15 цифрам (алгоритм ФОМС).
+///
+/// Цифры на нечётных местах, считая справа, записываются подряд в число, которое
+/// умножается на 2; слева к результату приписываются цифры с чётных мест. Контрольная
+/// цифра дополняет сумму всех цифр получившегося числа до кратного 10.
+pub fn check_digit(digits: &[u8]) -> u8 {
+    let odd = digits.iter().rev().step_by(2).fold(0u64, |acc, d| acc * 10 + *d as u64) * 2;
+    let even = digits.iter().rev().skip(1).step_by(2).map(|d| *d as u32).sum::<u32>();
+
+    let odd_sum = odd.to_string().bytes().map(|b| (b - b'0') as u32).sum::<u32>();
+    ((10 - (odd_sum + even) % 10) % 10) as u8
+}
+
+/// ЕНП, сохранённый числом, в виде строки для ЕМИАС и сообщений. В числе ведущие нули
+/// теряются, поэтому номер дополняется нулями слева до 16 цифр.
+pub fn format(oms: i64) -> String {
+    format!("{:0width$}", oms, width = ENP_LEN)
+}
+
+/// Проверяет номер полиса ОМС и возвращает ЕНП в виде числа (см. [`format`]).
+pub fn parse(text: &str) -> Result<i64, OmsError> {
+    let normalized = normalize(text);
+    if normalized.is_empty() {
+        return Err(OmsError::Empty);
+    }
+
+    if let Some(c) = normalized.chars().find(|c| !c.is_ascii_digit()) {
+        return match c.is_alphabetic() {
+            true => Err(OmsError::OldFormat),
+            false => Err(OmsError::InvalidChar(c))
+        };
+    }
+
+    let digits = normalized.bytes().map(|b| b - b'0').collect::<Vec<u8>>();
+    match digits.len() {
+        ENP_LEN => {},
+        TEMPORARY_LEN => return Err(OmsError::TemporaryCertificate),
+        10..=14 => return Err(OmsError::OldFormat),
+        len => return Err(OmsError::WrongLength(len))
+    }
+
+    let expected = check_digit(&digits[..ENP_LEN - 1]);
+    let actual = digits[ENP_LEN - 1];
+    if expected != actual {
+        return Err(OmsError::CheckDigit { expected, actual });
+    }
+
+    normalized.parse().map_err(|_| OmsError::WrongLength(digits.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = "7788899730000765";
+
+    fn digits(text: &str) -> Vec<u8> {
+        text.bytes().map(|b| b - b'0').collect()
+    }
+
+    #[test]
+    fn check_digit_of_valid_enp() {
+        assert_eq!(check_digit(&digits(&VALID[..15])), 5);
+    }
+
+    #[test]
+    fn parses_valid_enp() {
+        assert_eq!(parse(VALID), Ok(7788899730000765));
+        assert_eq!(parse("7788 8997-3000 0765"), Ok(7788899730000765));
+    }
+
+    #[test]
+    fn rejects_typo() {
+        assert_eq!(parse("7788899730000766"), Err(OmsError::CheckDigit { expected: 5, actual: 6 }));
+        assert!(matches!(parse("7788899370000765"), Err(OmsError::CheckDigit { .. })));
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        assert_eq!(parse(""), Err(OmsError::Empty));
+        assert_eq!(parse("123456789"), Err(OmsError::TemporaryCertificate));
+        assert_eq!(parse("АБ 123456"), Err(OmsError::OldFormat));
+        assert_eq!(parse("770012345678"), Err(OmsError::OldFormat));
+        assert_eq!(parse("778889973000076"), Err(OmsError::WrongLength(15)));
+        assert_eq!(parse("77888997300007651"), Err(OmsError::WrongLength(17)));
+        assert_eq!(parse("7788899730000765."), Err(OmsError::InvalidChar('.')));
+    }
+
+    #[test]
+    fn leading_zero_survives_round_trip() {
+        let enp = format!("012345678901234{}", check_digit(&digits("012345678901234")));
+
+        let parsed = parse(&enp).unwrap();
+        assert_eq!(format(parsed), enp);
+        assert_eq!(format(7788899730000765), VALID);
+    }
+}
@@ -0,0 +1,226 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+
+use super::basic::BasicRequest;
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct CreateAppointmentParamsRequest {
+    omsNumber: String,
+    birthDate: String,
+    availableResourceId: u64,
+    complexResourceId: u64,
+    referralId: u64,
+    startTime: String,
+    endTime: String
+}
+
+impl BasicRequest<CreateAppointmentParamsRequest> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id:Option<String>,
+        oms_number: String,
+        birth_date:String,
+        available_resource_id: u64,
+        complex_resource_id: u64,
+        referral_id: u64,
+        start_time: &DateTime<FixedOffset>,
+        end_time: &DateTime<FixedOffset>,
+    ) -> Self {
+        Self {
+            id,
+            jsonrpc: "2.0".to_string(),
+            method: "createAppointment".to_string(),
+            params: CreateAppointmentParamsRequest {
+                omsNumber: oms_number,
+                birthDate: birth_date,
+                availableResourceId: available_resource_id,
+                complexResourceId: complex_resource_id,
+                referralId: referral_id,
+                startTime: start_time.to_rfc3339(),
+                endTime: end_time.to_rfc3339()
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+pub struct CreateAppointmentResponse {
+    pub result: CreatedAppointment
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct CreatedAppointment {
+    pub appointment_id: u64
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct AppointmentsInfoParamsRequest {
+    omsNumber: String,
+    birthDate: String
+}
+
+impl BasicRequest<AppointmentsInfoParamsRequest> {
+    pub fn new(
+        id:Option<String>,
+        oms_number: String,
+        birth_date:String
+    ) -> Self {
+        Self {
+            id,
+            jsonrpc: "2.0".to_string(),
+            method: "getAppointmentReceptionsByPatient".to_string(),
+            params: AppointmentsInfoParamsRequest {
+                omsNumber: oms_number,
+                birthDate: birth_date
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+pub struct AppointmentsInfoResponse {
+    pub result: AppointmentsInfo
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+pub struct AppointmentsInfo {
+    #[serde(default)]
+    pub appointment: Vec<AppointmentInfo>
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct AppointmentInfo {
+    pub id: u64,
+    pub start_time: DateTime<FixedOffset>,
+    pub end_time: DateTime<FixedOffset>,
+    pub available_resource_id: u64,
+    pub complex_resource_id: u64,
+    pub referral_id: Option<u64>,
+    pub lpu_short_name: Option<String>,
+    pub lpu_address: Option<String>,
+    pub room_number: Option<String>,
+    pub to_doctor: Option<AppointmentDoctor>,
+    pub to_ldp: Option<AppointmentLdp>
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct AppointmentDoctor {
+    pub speciality_name: String,
+    pub doctor_fio: Option<String>
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct AppointmentLdp {
+    pub ldp_type_name: String
+}
+
+impl AppointmentInfo {
+    pub fn name(&self) -> String {
+        match (&self.to_doctor, &self.to_ldp) {
+            (Some(doctor), _) => match &doctor.doctor_fio {
+                Some(fio) => format!("{} ({})", doctor.speciality_name, fio),
+                None => doctor.speciality_name.clone()
+            },
+            (None, Some(ldp)) => ldp.ldp_type_name.clone(),
+            (None, None) => "Приём".to_string()
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct CancelAppointmentParamsRequest {
+    omsNumber: String,
+    birthDate: String,
+    appointmentId: u64
+}
+
+impl BasicRequest<CancelAppointmentParamsRequest> {
+    pub fn new(
+        id:Option<String>,
+        oms_number: String,
+        birth_date:String,
+        appointment_id: u64
+    ) -> Self {
+        Self {
+            id,
+            jsonrpc: "2.0".to_string(),
+            method: "cancelAppointment".to_string(),
+            params: CancelAppointmentParamsRequest {
+                omsNumber: oms_number,
+                birthDate: birth_date,
+                appointmentId: appointment_id
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+pub struct CancelAppointmentResponse {
+    pub result: bool
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct ShiftAppointmentParamsRequest {
+    omsNumber: String,
+    birthDate: String,
+    appointmentId: u64,
+    availableResourceId: u64,
+    complexResourceId: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    referralId: Option<u64>,
+    startTime: String,
+    endTime: String
+}
+
+impl BasicRequest<ShiftAppointmentParamsRequest> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id:Option<String>,
+        oms_number: String,
+        birth_date:String,
+        appointment_id: u64,
+        available_resource_id: u64,
+        complex_resource_id: u64,
+        referral_id: Option<u64>,
+        start_time: &DateTime<FixedOffset>,
+        end_time: &DateTime<FixedOffset>,
+    ) -> Self {
+        Self {
+            id,
+            jsonrpc: "2.0".to_string(),
+            method: "shiftAppointment".to_string(),
+            params: ShiftAppointmentParamsRequest {
+                omsNumber: oms_number,
+                birthDate: birth_date,
+                appointmentId: appointment_id,
+                availableResourceId: available_resource_id,
+                complexResourceId: complex_resource_id,
+                referralId: referral_id,
+                startTime: start_time.to_rfc3339(),
+                endTime: end_time.to_rfc3339()
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+pub struct ShiftAppointmentResponse {
+    pub result: CreatedAppointment
+}
@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize)]
 pub struct BasicRequest<T> {
@@ -7,3 +7,20 @@ pub struct BasicRequest<T> {
     pub method: String,
     pub params: T
 }
+
+/// Оболочка JSON-RPC ответа без поля `result`: позволяет отличить ошибку ЕМИАС
+/// от успешного ответа до разбора данных конкретного метода.
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+pub struct BasicResponse {
+    pub id: Option<String>,
+    pub error: Option<RpcError>
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    #[serde(default)]
+    pub message: String
+}
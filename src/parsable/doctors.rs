@@ -121,11 +121,7 @@ pub trait HasComplexResource {
     fn complex_resource(&self) -> &Vec<ComplexResource>;
 
     fn is_room(c_r:ComplexResource) -> bool {
-        if c_r.room.is_some()  {
-            true
-        } else {
-            false
-        }
+        c_r.room.is_some()
     }
 }
 
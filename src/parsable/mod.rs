@@ -2,4 +2,8 @@ pub mod referrals;
 
 pub mod doctors;
 
+pub mod schedule;
+
+pub mod appointments;
+
 pub mod basic;
\ No newline at end of file
@@ -0,0 +1,89 @@
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use super::basic::BasicRequest;
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct ScheduleInfoParamsRequest {
+    omsNumber: String,
+    birthDate: String,
+    availableResourceId: u64,
+    complexResourceId: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    referralId: Option<u64>
+}
+
+impl BasicRequest<ScheduleInfoParamsRequest> {
+    pub fn new(
+        id:Option<String>,
+        oms_number: String,
+        birth_date:String,
+        available_resource_id: u64,
+        complex_resource_id: u64,
+        referral_id: Option<u64>,
+    ) -> Self {
+        Self {
+            id,
+            jsonrpc: "2.0".to_string(),
+            method: "getAvailableResourceScheduleInfo".to_string(),
+            params: ScheduleInfoParamsRequest {
+                omsNumber: oms_number,
+                birthDate: birth_date,
+                availableResourceId: available_resource_id,
+                complexResourceId: complex_resource_id,
+                referralId: referral_id
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+pub struct ScheduleInfoResponse {
+    pub result: ScheduleInfo
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct ScheduleInfo {
+    pub id: u64,
+    pub lpu_id: u64,
+    #[serde(default)]
+    pub schedule_of_day: Vec<ScheduleOfDay>
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct ScheduleOfDay {
+    pub date: NaiveDate,
+    #[serde(default)]
+    pub schedule_by_slot: Vec<ScheduleBySlot>
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct ScheduleBySlot {
+    #[serde(default)]
+    pub slot: Vec<Slot>,
+    pub cabinet_number: Option<String>,
+    pub lpu_short_name: Option<String>,
+    pub lpu_address: Option<String>
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct Slot {
+    pub start_time: DateTime<FixedOffset>,
+    pub end_time: DateTime<FixedOffset>
+}
+
+impl ScheduleOfDay {
+    pub fn slots(&self) -> impl Iterator<Item = &Slot> {
+        self.schedule_by_slot.iter().flat_map(|s| s.slot.iter())
+    }
+}
@@ -0,0 +1,795 @@
+use std::{collections::HashMap, env, str::FromStr};
+
+use rand::Rng;
+
+use sea_orm::{prelude::*, ActiveValue, QueryOrder, QuerySelect};
+use teloxide::{prelude::*, types::InlineKeyboardMarkup};
+use tokio::{sync::watch, task::{JoinError, JoinSet}, time::Duration};
+
+use crate::em_commands::callback_data::{profile_button, CallbackAction};
+use crate::entities::{availability, info, prelude::*, watch_rule};
+use crate::error::EmiasError;
+use crate::helper::{available_dates, collect_availability_changes, get_user_availability, AvailabilitySnapshot, FreeRoom, ReferralAvailability};
+use crate::message_builder::{send_parts, MessageBuilder};
+use crate::parsable::appointments::AppointmentInfo;
+use crate::profiles::label;
+use crate::watch::rules_filter;
+use crate::verification::{self, Verification};
+use crate::{appointment_reminders, auto_book, oms, reminders, DB, EMIAS};
+
+/// Настройки планировщика опроса ЕМИАС.
+#[derive(Debug, Clone)]
+pub struct PollerConfig {
+    /// Как часто опрашивать каждого пользователя.
+    pub interval: Duration,
+    /// Случайный разброс следующей проверки в обе стороны, чтобы пользователи не
+    /// собирались в одну волну.
+    pub jitter: Duration,
+    /// Сколько пользователей опрашивается одновременно.
+    pub user_concurrency: usize,
+    /// Сколько направлений одного пользователя опрашивается одновременно.
+    pub referral_concurrency: usize,
+    /// После скольких отказов подряд из-за данных пациента опрос приостанавливается
+    /// до изменения профиля.
+    pub max_failures: i32,
+    /// Верхняя граница экспоненциальной задержки после неудачных проверок.
+    pub max_backoff: Duration,
+}
+
+impl Default for PollerConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60*30),
+            jitter: Duration::from_secs(60),
+            user_concurrency: 8,
+            referral_concurrency: 4,
+            max_failures: 5,
+            max_backoff: Duration::from_secs(60*60*24),
+        }
+    }
+}
+
+impl PollerConfig {
+    /// Читает настройки из переменных окружения `POLL_INTERVAL`, `POLL_JITTER`,
+    /// `POLL_MAX_BACKOFF` (в секундах), `POLL_CONCURRENCY`, `POLL_REFERRAL_CONCURRENCY`
+    /// и `POLL_MAX_FAILURES`.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        Self {
+            interval: env_parse("POLL_INTERVAL").map_or(default.interval, Duration::from_secs),
+            jitter: env_parse("POLL_JITTER").map_or(default.jitter, Duration::from_secs),
+            user_concurrency: env_parse("POLL_CONCURRENCY").unwrap_or(default.user_concurrency).max(1),
+            referral_concurrency: env_parse("POLL_REFERRAL_CONCURRENCY").unwrap_or(default.referral_concurrency).max(1),
+            max_failures: env_parse("POLL_MAX_FAILURES").unwrap_or(default.max_failures).max(1),
+            max_backoff: env_parse("POLL_MAX_BACKOFF").map_or(default.max_backoff, Duration::from_secs),
+        }
+    }
+
+    /// Задержка первой проверки пользователя, которого бот ещё не опрашивал: случайный
+    /// момент интервала, так что пользователи равномерно распределяются по нему.
+    fn first_check(&self) -> Duration {
+        self.interval.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+
+    fn next_check(&self) -> Duration {
+        let jitter = self.jitter.as_secs_f64();
+        let shift = if jitter > 0.0 { rand::thread_rng().gen_range(-jitter..=jitter) } else { 0.0 };
+        Duration::from_secs_f64((self.interval.as_secs_f64() + shift).max(1.0))
+    }
+
+    /// Задержка после `failures` неудачных проверок подряд: интервал, удваивающийся
+    /// с каждой неудачей, но не больше `max_backoff`.
+    fn backoff(&self, failures: i32) -> Duration {
+        let factor = 2f64.powi(failures.saturating_sub(1).clamp(0, 30));
+        self.next_check().mul_f64(factor).min(self.max_backoff.max(self.interval))
+    }
+}
+
+fn env_parse<T: FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok().and_then(|v| v.parse().ok())
+}
+
+/// Как часто планировщик проверяет, чья очередь опроса подошла.
+const SCHEDULER_TICK: Duration = Duration::from_secs(5);
+
+/// Планировщик опроса ЕМИАС по всем пользователям с заполненным профилем. Пишет
+/// пользователю, только если свободные даты по его направлениям изменились.
+///
+/// У каждого пользователя своё время следующей проверки (`info.next_poll_at`); на каждом
+/// шаге из базы читаются только те, чья очередь подошла, и не больше, чем свободных мест
+/// из `user_concurrency`. Если проверки начинают опаздывать больше чем на интервал,
+/// планировщик пишет предупреждение в лог — значит, нужно поднять параллельность или
+/// лимиты клиента ЕМИАС.
+///
+/// Расписание хранится только в базе, поэтому после перезапуска опрос продолжается
+/// по нему. По сигналу `shutdown` новые проверки не начинаются, а уже начатые доводятся
+/// до конца.
+pub async fn run(bot: Bot, mut shutdown: watch::Receiver<bool>) {
+    let config = PollerConfig::from_env();
+    log::info!("Poller started: {:?}", config);
+
+    let mut in_flight: HashMap<tokio::task::Id, i32> = HashMap::new();
+    let mut tasks: JoinSet<()> = JoinSet::new();
+    let mut tick = tokio::time::interval(SCHEDULER_TICK);
+
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {
+                if let Err(err) = schedule_new_users(&config).await {
+                    log::error!("Failed to schedule new users for polling: {}", err);
+                }
+
+                let free = config.user_concurrency.saturating_sub(in_flight.len());
+                if free == 0 {
+                    continue;
+                }
+
+                let now = chrono::Utc::now().naive_utc();
+                let due = match due_users(now)
+                    .filter(info::Column::Id.is_not_in(in_flight.values().copied()))
+                    .order_by_asc(info::Column::NextPollAt)
+                    .limit(free as u64)
+                    .all(DB.get().unwrap())
+                    .await
+                {
+                    Ok(due) => due,
+                    Err(err) => {
+                        log::error!("Failed to load users for polling: {}", err);
+                        continue;
+                    }
+                };
+
+                for user in due {
+                    let lag = user.next_poll_at.map_or(chrono::Duration::zero(), |next_poll_at| now - next_poll_at);
+                    if lag.to_std().is_ok_and(|lag| lag > config.interval) {
+                        log::warn!("Polling is {}s behind schedule for chat {}", lag.num_seconds(), user.chat_id);
+                    }
+
+                    let bot = bot.clone();
+                    let config = config.clone();
+                    let user_id = user.id;
+                    let handle = tasks.spawn(async move {
+                        let result = match user.verified {
+                            true => poll_user(&bot, &user, config.referral_concurrency).await,
+                            false => verify_and_poll(&bot, &user, config.referral_concurrency).await
+                        };
+                        record_poll(&bot, &user, &config, result).await;
+                    });
+                    in_flight.insert(handle.id(), user_id);
+                }
+            },
+            Some(finished) = tasks.join_next_with_id() => {
+                finish_poll(finished, &config, &mut in_flight).await;
+            },
+            _ = shutdown.changed() => break
+        }
+    }
+
+    log::info!("Poller is shutting down, waiting for {} in-flight checks", in_flight.len());
+    while let Some(finished) = tasks.join_next_with_id().await {
+        finish_poll(finished, &config, &mut in_flight).await;
+    }
+    log::info!("Poller stopped");
+}
+
+/// Пользователи, которых нужно опрашивать. Сюда входят и ещё не подтверждённые в ЕМИАС
+/// профили без паузы: их проверка не удалась из-за сбоя ЕМИАС и повторяется поллером.
+fn pollable() -> Select<Info> {
+    Info::find()
+        .filter(info::Column::OmsCard.is_not_null())
+        .filter(info::Column::DateBirth.is_not_null())
+        .filter(info::Column::PollingPaused.eq(false))
+}
+
+/// Пользователи, чья очередь опроса подошла к моменту `now`.
+fn due_users(now: DateTime) -> Select<Info> {
+    pollable().filter(info::Column::NextPollAt.lte(now))
+}
+
+/// Назначает первую проверку пользователям, которых бот ещё не опрашивал.
+async fn schedule_new_users(config: &PollerConfig) -> Result<(), DbErr> {
+    let db = DB.get().unwrap();
+    let users = pollable().filter(info::Column::NextPollAt.is_null()).all(db).await?;
+
+    for user in users {
+        let mut state: info::ActiveModel = user.into();
+        state.next_poll_at = ActiveValue::Set(Some(poll_at(config.first_check())));
+        state.update(db).await?;
+    }
+
+    Ok(())
+}
+
+fn poll_at(delay: Duration) -> DateTime {
+    chrono::Utc::now().naive_utc() + chrono::Duration::from_std(delay).unwrap_or_default()
+}
+
+/// Убирает завершённую проверку из выполняющихся. Если задача проверки упала, итог не
+/// записан, поэтому следующая проверка назначается здесь, иначе пользователь опрашивался
+/// бы на каждом шаге планировщика.
+async fn finish_poll(finished: Result<(tokio::task::Id, ()), JoinError>, config: &PollerConfig, in_flight: &mut HashMap<tokio::task::Id, i32>) {
+    let err = match finished {
+        Ok((task_id, ())) => {
+            in_flight.remove(&task_id);
+            return;
+        },
+        Err(err) => err
+    };
+
+    log::error!("Polling task failed: {}", err);
+    let Some(user_id) = in_flight.remove(&err.id()) else {
+        return;
+    };
+
+    let updated = Info::update_many()
+        .col_expr(info::Column::NextPollAt, Expr::value(poll_at(config.next_check())))
+        .filter(info::Column::Id.eq(user_id))
+        .exec(DB.get().unwrap())
+        .await;
+    if let Err(err) = updated {
+        log::error!("Failed to reschedule polling for user {}: {}", user_id, err);
+    }
+}
+
+/// Сохраняет итог проверки: время последней и следующей проверки и счётчики неудач.
+///
+/// Если ЕМИАС отвергает данные пациента, пользователь один раз получает подсказку
+/// проверить `/omscard` и `/datebirth`, а после `max_failures` таких отказов подряд опрос
+/// приостанавливается до изменения профиля (см. [`reset_failures`]). Временные сбои
+/// ЕМИАС только увеличивают задержку и не приближают паузу.
+///
+/// Пока шла проверка, пользователь мог изменить профиль, поэтому счётчики берутся из
+/// свежей строки, а итог проверки старых данных не записывается вовсе.
+async fn record_poll(bot: &Bot, polled: &info::Model, config: &PollerConfig, result: Result<(), EmiasError>) {
+    let user = match Info::find_by_id(polled.id).one(DB.get().unwrap()).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return,
+        Err(err) => {
+            log::error!("Failed to reload poll state for chat {}: {}", polled.chat_id, err);
+            return;
+        }
+    };
+    if (user.oms_card, user.date_birth) != (polled.oms_card, polled.date_birth) {
+        log::info!("Profile of chat {} changed during polling, result discarded", user.chat_id);
+        return;
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let mut state: info::ActiveModel = user.clone().into();
+    state.last_polled_at = ActiveValue::Set(Some(now));
+
+    let delay = match result {
+        Ok(()) => {
+            if user.failure_count > 0 || user.profile_failure_count > 0 || user.failure_notified {
+                state.failure_count = ActiveValue::Set(0);
+                state.profile_failure_count = ActiveValue::Set(0);
+                state.last_error = ActiveValue::Set(None);
+                state.failure_notified = ActiveValue::Set(false);
+            }
+            config.next_check()
+        },
+        Err(err) => {
+            let failures = user.failure_count.saturating_add(1);
+            log::warn!("Polling referrals for chat {} failed ({} in a row): {}", user.chat_id, failures, err);
+
+            state.failure_count = ActiveValue::Set(failures);
+            state.last_error = ActiveValue::Set(Some(err.to_string()));
+            state.last_failed_at = ActiveValue::Set(Some(now));
+
+            if err.is_profile_error() {
+                let profile_failures = user.profile_failure_count.saturating_add(1);
+                state.profile_failure_count = ActiveValue::Set(profile_failures);
+
+                if !user.failure_notified {
+                    let sent = bot.send_message(
+                        ChatId(user.chat_id), 
+                        label(&user, format!(
+                            "Не удалось проверить ваши направления. \n{} \nЕсли ошибка повторится {} раз подряд, проверка приостановится до изменения полиса или даты рождения.", 
+                            err.user_message(), config.max_failures
+                        ))
+                    ).await;
+                    match sent {
+                        Ok(_) => state.failure_notified = ActiveValue::Set(true),
+                        Err(err) => log::error!("Failed to send polling error to chat {}: {}", user.chat_id, err)
+                    }
+                }
+
+                if profile_failures >= config.max_failures {
+                    log::info!("Polling paused for chat {} after {} profile errors", user.chat_id, profile_failures);
+                    state.polling_paused = ActiveValue::Set(true);
+                }
+            }
+
+            config.backoff(failures)
+        }
+    };
+
+    state.next_poll_at = ActiveValue::Set(Some(poll_at(delay)));
+    if let Err(err) = state.update(DB.get().unwrap()).await {
+        log::error!("Failed to save poll state for chat {}: {}", user.chat_id, err);
+    }
+}
+
+/// Сколько поллер не берёт профиль, данные которого проверяет обработчик команды. Срок
+/// больше любого запроса к ЕМИАС с повторами; если ЕМИАС недоступен, по его истечении
+/// проверку повторит поллер.
+const VERIFICATION_HOLD: Duration = Duration::from_secs(10*60);
+
+/// Сбрасывает счётчики неудач и снимает паузу опроса после изменения профиля. Время
+/// следующей проверки не меняется: см. [`hold_for_verification`].
+pub fn reset_failures(profile: &mut info::ActiveModel) {
+    profile.failure_count = ActiveValue::Set(0);
+    profile.profile_failure_count = ActiveValue::Set(0);
+    profile.last_error = ActiveValue::Set(None);
+    profile.failure_notified = ActiveValue::Set(false);
+    profile.polling_paused = ActiveValue::Set(false);
+}
+
+/// Откладывает опрос профиля, пока обработчик проверяет его данные в ЕМИАС, чтобы поллер
+/// не проверял их параллельно. После успешной проверки опрос назначается сразу.
+pub fn hold_for_verification(profile: &mut info::ActiveModel) {
+    profile.next_poll_at = ActiveValue::Set(Some(poll_at(VERIFICATION_HOLD)));
+}
+
+/// Повторная проверка профиля, который не удалось подтвердить из-за сбоя ЕМИАС. Если
+/// ЕМИАС узнал пациента, сразу опрашиваем его направления; если снова недоступен —
+/// ошибка, и следующая попытка будет с увеличенной задержкой. Об отказе и успехе
+/// пользователь узнаёт так же, как при вводе данных.
+async fn verify_and_poll(bot: &Bot, user: &info::Model, referral_concurrency: usize) -> Result<(), EmiasError> {
+    let verification = match verification::verify(user).await {
+        Ok(Some(verification)) => verification,
+        Ok(None) => return Ok(()),
+        Err(err) => {
+            log::error!("Failed to store verification of profile {}: {}", user.id, err);
+            return Ok(());
+        }
+    };
+
+    match verification {
+        Verification::Unavailable(err) => Err(err),
+        verification @ Verification::Rejected(_) => {
+            verification::report(bot, user, &verification).await;
+            Ok(())
+        },
+        verification @ Verification::Verified(_) => {
+            verification::report(bot, user, &verification).await;
+            poll_user(bot, user, referral_concurrency).await
+        }
+    }
+}
+
+/// Одна проверка пользователя. Ошибкой считается отказ ЕМИАС отдать направления или
+/// записи: без списка записей нельзя ни безопасно автозаписываться, ни напоминать, поэтому
+/// такая проверка целиком повторяется с отступом, как и при других сбоях. Остальные шаги
+/// (напоминания, автозапись, снимок доступности) пишут свои ошибки в лог.
+async fn poll_user(bot: &Bot, user: &info::Model, referral_concurrency: usize) -> Result<(), EmiasError> {
+    let rules = match WatchRule::find().filter(watch_rule::Column::InfoId.eq(user.id)).all(DB.get().unwrap()).await {
+        Ok(rules) => rules,
+        Err(err) => {
+            log::error!("Failed to load watch rules for chat {}: {}", user.chat_id, err);
+            return Ok(());
+        }
+    };
+
+    let referrals = get_user_availability(user, referral_concurrency).await?;
+
+    let mut appointments = get_appointments(user).await?;
+
+    if let Err(err) = appointment_reminders::sync(user, &appointments).await {
+        log::error!("Failed to sync appointment reminders for chat {}: {}", user.chat_id, err);
+    }
+
+    // Напоминания о направлениях должны видеть новую запись, иначе только что
+    // использованное направление выглядело бы свободным.
+    if auto_book::run(bot, user, &rules, &referrals, &appointments).await {
+        appointments = get_appointments(user).await?;
+    }
+
+    if let Err(err) = reminders::run(bot, user, &referrals, &appointments).await {
+        log::error!("Failed to process referral reminders for chat {}: {}", user.chat_id, err);
+    }
+
+    let changes = match store_availability(user, &rules, &referrals).await {
+        Ok(changes) => changes,
+        Err(err) => {
+            log::error!("Failed to store availability for chat {}: {}", user.chat_id, err);
+            return Ok(());
+        }
+    };
+
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    let go_to_ref_button = profile_button(
+        "Записаться", 
+        user.id,
+        CallbackAction::Referrals { page: 0 }
+    );
+    let markup = InlineKeyboardMarkup::new([[go_to_ref_button]]);
+
+    let mut message = MessageBuilder::new();
+    message.push(label(user, "Изменилась запись по направлениям: \n"));
+    for change in changes {
+        message.push(format!("{}\n", change));
+    }
+
+    // Ошибка уже записана в лог; снимок сохранён, поэтому повторно об этих
+    // изменениях не сообщим.
+    let _ = send_parts(bot, ChatId(user.chat_id), message.build(), Some(markup)).await;
+
+    Ok(())
+}
+
+async fn get_appointments(user: &info::Model) -> Result<Vec<AppointmentInfo>, EmiasError> {
+    let appointments = EMIAS.get().unwrap()
+        .get_appointments_info(&oms::format(user.oms_card.unwrap()), &user.date_birth.unwrap())
+        .await?;
+
+    Ok(appointments.result.appointment)
+}
+
+/// Сохраняет текущий снимок свободных дат по каждому направлению и возвращает описание
+/// изменений относительно прошлого опроса, сгруппированное по направлениям.
+///
+/// В снимке хранятся все кабинеты направления. Правила отслеживания (`/watch`) применяются
+/// при сравнении одинаково к старому и новому снимку, поэтому добавление или удаление
+/// правила само по себе не выглядит как изменение записи.
+async fn store_availability(user: &info::Model, rules: &[watch_rule::Model], referrals: &[ReferralAvailability]) -> Result<Vec<String>, DbErr> {
+    let db = DB.get().unwrap();
+    let now = chrono::Utc::now().naive_utc();
+
+    let filter = rules_filter(rules);
+
+    let mut stored = Availability::find()
+        .filter(availability::Column::InfoId.eq(user.id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| (row.referral_id, row))
+        .collect::<HashMap<i64, availability::Model>>();
+
+    let mut changes = vec![];
+
+    for referral in referrals {
+        let snapshot = serde_json::to_string(&referral.rooms).unwrap();
+
+        let referral_changes = match stored.remove(&(referral.referral_id as i64)) {
+            Some(row) if row.snapshot == snapshot => continue,
+            Some(row) => {
+                let referral_changes = availability_changes(Some(&row.snapshot), referral, &filter);
+
+                let mut row: availability::ActiveModel = row.into();
+                row.snapshot = ActiveValue::Set(snapshot);
+                row.updated_at = ActiveValue::Set(now);
+                row.update(db).await?;
+
+                referral_changes
+            },
+            None => {
+                Availability::insert(availability::ActiveModel {
+                    info_id: ActiveValue::Set(user.id),
+                    referral_id: ActiveValue::Set(referral.referral_id as i64),
+                    snapshot: ActiveValue::Set(snapshot),
+                    updated_at: ActiveValue::Set(now),
+                    ..Default::default()
+                }).exec(db).await?;
+
+                availability_changes(None, referral, &filter)
+            }
+        };
+
+        if !referral_changes.is_empty() {
+            changes.push(format!("{}: \n{}", referral.name, referral_changes.join("\n")));
+        }
+    }
+
+    // Направления, которых больше нет в ЕМИАС (истекли или использованы).
+    for row in stored.into_values() {
+        row.delete(db).await?;
+    }
+
+    Ok(changes)
+}
+
+/// Изменения дат направления относительно сохранённого снимка (`None` — направление новое).
+/// Снимок старого формата, где хранились только уже отфильтрованные даты, сравнивать не с
+/// чем: он молча заменяется новым.
+fn availability_changes(stored: Option<&str>, referral: &ReferralAvailability, filter: impl Fn(u64, &FreeRoom) -> bool) -> Vec<String> {
+    let old = match stored.map(serde_json::from_str::<Vec<FreeRoom>>) {
+        None => AvailabilitySnapshot::new(),
+        Some(Ok(rooms)) => available_dates(referral.referral_id, &rooms, &filter),
+        Some(Err(_)) => return vec![]
+    };
+
+    collect_availability_changes(&old, &referral.snapshot(&filter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{block_on, bot, emias_calls, insert_profile, sent_texts};
+
+    fn config() -> PollerConfig {
+        PollerConfig { jitter: Duration::ZERO, max_failures: 2, ..PollerConfig::default() }
+    }
+
+    fn patient_error() -> EmiasError {
+        EmiasError::Rpc { request_id: "test".to_string(), code: 1001, message: "Пациент не найден".to_string() }
+    }
+
+    fn service_error() -> EmiasError {
+        EmiasError::Status { request_id: "test".to_string(), status: reqwest::StatusCode::SERVICE_UNAVAILABLE }
+    }
+
+    async fn reload(user: &info::Model) -> info::Model {
+        Info::find_by_id(user.id).one(DB.get().unwrap()).await.unwrap().unwrap()
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_limit() {
+        let config = config();
+
+        assert_eq!(config.backoff(1), config.interval);
+        assert_eq!(config.backoff(3), config.interval * 4);
+        assert_eq!(config.backoff(30), config.max_backoff);
+    }
+
+    fn room(resource_id: u64, date: chrono::NaiveDate) -> FreeRoom {
+        FreeRoom {
+            resource_id,
+            complex_resource_id: resource_id,
+            resource_name: format!("Врач {}", resource_id),
+            lpu_id: 1,
+            lpu_short_name: "ГП № 2".to_string(),
+            date
+        }
+    }
+
+    fn watch_doctor(doctor_id: i64) -> watch_rule::Model {
+        watch_rule::Model {
+            id: 1,
+            info_id: 1,
+            referral_id: None,
+            doctor_id: Some(doctor_id),
+            doctor_name: None,
+            lpu_id: None,
+            date_from: None,
+            date_to: None,
+            created_at: Default::default(),
+            auto_book: false,
+        }
+    }
+
+    #[test]
+    fn rule_changes_do_not_look_like_availability_changes() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 9, 20).unwrap();
+        let referral = ReferralAvailability { referral_id: 1, name: "Кардиолог".to_string(), end_date: None, rooms: vec![room(1, date), room(2, date.succ_opt().unwrap())] };
+        let stored = serde_json::to_string(&referral.rooms).unwrap();
+
+        // Снимок сохранён без правил, теперь пользователь следит только за врачом 2 — и наоборот.
+        let rules = [watch_doctor(2)];
+        assert!(availability_changes(Some(&stored), &referral, rules_filter(&rules)).is_empty());
+        assert!(availability_changes(Some(&stored), &referral, rules_filter(&[])).is_empty());
+
+        let old = ReferralAvailability { rooms: vec![room(1, date)], ..referral };
+        let stored = serde_json::to_string(&old.rooms).unwrap();
+        let new = ReferralAvailability { rooms: vec![room(1, date), room(2, date.succ_opt().unwrap())], ..old };
+        assert_eq!(availability_changes(Some(&stored), &new, rules_filter(&rules)).len(), 1);
+        assert!(availability_changes(Some(&stored), &new, rules_filter(&[watch_doctor(1)])).is_empty());
+    }
+
+    #[test]
+    fn dates_before_rule_window_are_not_reported() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 11, 20).unwrap();
+        let from = chrono::NaiveDate::from_ymd_opt(2024, 12, 1).unwrap();
+        let rules = [watch_rule::Model { doctor_id: None, date_from: Some(from), ..watch_doctor(0) }];
+
+        let referral = ReferralAvailability { referral_id: 1, name: "Кардиолог".to_string(), end_date: None, rooms: vec![room(1, date)] };
+        assert!(availability_changes(None, &referral, rules_filter(&rules)).is_empty());
+
+        let referral = ReferralAvailability { rooms: vec![room(1, date), room(2, from)], ..referral };
+        let changes = availability_changes(None, &referral, rules_filter(&rules));
+        assert_eq!(changes, ["+1 новая дата в ГП № 2: 01.12.2024"]);
+    }
+
+    #[test]
+    fn old_snapshot_format_is_replaced_silently() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 9, 20).unwrap();
+        let referral = ReferralAvailability { referral_id: 1, name: "Кардиолог".to_string(), end_date: None, rooms: vec![room(1, date)] };
+
+        assert!(availability_changes(Some(r#"{"ГП № 1":["2024-09-01"]}"#), &referral, rules_filter(&[])).is_empty());
+        assert_eq!(availability_changes(None, &referral, rules_filter(&[])).len(), 1);
+    }
+
+    #[test]
+    fn poll_stores_availability_per_referral() {
+        block_on(async {
+            let user = insert_profile(101).await;
+
+            poll_user(&bot(), &user, 2).await.unwrap();
+
+            let rows = Availability::find()
+                .filter(availability::Column::InfoId.eq(user.id))
+                .all(DB.get().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(rows.len(), 2);
+        });
+    }
+
+    #[test]
+    fn appointments_are_refetched_after_auto_book() {
+        block_on(async {
+            let user = insert_profile(110).await;
+            let rule = watch_rule::ActiveModel {
+                info_id: ActiveValue::Set(user.id),
+                referral_id: ActiveValue::Set(Some(172704541983)),
+                auto_book: ActiveValue::Set(true),
+                created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+                ..Default::default()
+            }.insert(DB.get().unwrap()).await.unwrap();
+            let before = emias_calls("getAppointmentReceptionsByPatient");
+
+            poll_user(&bot(), &user, 2).await.unwrap();
+
+            assert!(sent_texts(110).iter().any(|text| text.contains("вы записаны")));
+            let rule = WatchRule::find_by_id(rule.id).one(DB.get().unwrap()).await.unwrap().unwrap();
+            assert!(!rule.auto_book);
+            // Список записей запрошен второй раз, уже с новой записью.
+            assert!(emias_calls("getAppointmentReceptionsByPatient") - before >= 2);
+        });
+    }
+
+    #[test]
+    fn new_users_are_spread_over_the_interval() {
+        block_on(async {
+            let config = config();
+            let user = insert_profile(105).await;
+            let before = chrono::Utc::now().naive_utc();
+
+            schedule_new_users(&config).await.unwrap();
+
+            let next_poll_at = reload(&user).await.next_poll_at.unwrap();
+            assert!(next_poll_at >= before);
+            assert!(next_poll_at <= poll_at(config.interval));
+
+            // Пользователь уже в расписании и не переназначается на следующем шаге.
+            schedule_new_users(&config).await.unwrap();
+            assert_eq!(reload(&user).await.next_poll_at, Some(next_poll_at));
+        });
+    }
+
+    #[test]
+    fn profile_being_verified_is_not_due() {
+        block_on(async {
+            let user = insert_profile(109).await;
+
+            // Пользователь исправил полис командой `/omscard`, проверка ещё идёт.
+            let mut changed: info::ActiveModel = user.clone().into();
+            changed.verified = ActiveValue::Set(false);
+            reset_failures(&mut changed);
+            hold_for_verification(&mut changed);
+            changed.update(DB.get().unwrap()).await.unwrap();
+
+            let now = chrono::Utc::now().naive_utc();
+            let due = due_users(now).filter(info::Column::Id.eq(user.id)).count(DB.get().unwrap()).await.unwrap();
+            assert_eq!(due, 0);
+
+            // Если ЕМИАС так и не ответил, после паузы проверку повторит поллер.
+            let later = poll_at(VERIFICATION_HOLD) + chrono::Duration::seconds(1);
+            let due = due_users(later).filter(info::Column::Id.eq(user.id)).count(DB.get().unwrap()).await.unwrap();
+            assert_eq!(due, 1);
+        });
+    }
+
+    #[test]
+    fn appointments_failure_fails_the_poll() {
+        block_on(async {
+            let mut user = insert_profile(104).await;
+            let mut changed: info::ActiveModel = user.clone().into();
+            changed.date_birth = ActiveValue::Set(Some(Date::from_ymd_opt(2001, 11, 20).unwrap()));
+            user = changed.update(DB.get().unwrap()).await.unwrap();
+
+            assert!(poll_user(&bot(), &user, 2).await.is_err());
+
+            // Проверка повторится целиком, поэтому снимок не сохраняется.
+            let rows = Availability::find()
+                .filter(availability::Column::InfoId.eq(user.id))
+                .count(DB.get().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(rows, 0);
+        });
+    }
+
+    /// Профиль, который ещё не подтверждён в ЕМИАС; дата рождения выбирает ответ мок-сервера.
+    async fn unverified_profile(chat_id: i64, day: u32) -> info::Model {
+        let mut profile: info::ActiveModel = insert_profile(chat_id).await.into();
+        profile.date_birth = ActiveValue::Set(Some(Date::from_ymd_opt(2001, 11, day).unwrap()));
+        profile.verified = ActiveValue::Set(false);
+        profile.update(DB.get().unwrap()).await.unwrap()
+    }
+
+    #[test]
+    fn pending_verification_is_retried_by_poller() {
+        block_on(async {
+            let (bot, config) = (bot(), config());
+
+            // ЕМИАС недоступен: профиль остаётся в опросе, следующая попытка — с задержкой.
+            let user = unverified_profile(106, 21).await;
+            let result = verify_and_poll(&bot, &user, 2).await;
+            assert!(result.is_err());
+            record_poll(&bot, &user, &config, result).await;
+            let state = reload(&user).await;
+            assert_eq!((state.verified, state.polling_paused, state.failure_count), (false, false, 1));
+
+            // ЕМИАС узнал пациента: профиль подтверждён и сразу опрошен.
+            let user = unverified_profile(107, 19).await;
+            verify_and_poll(&bot, &user, 2).await.unwrap();
+            assert!(reload(&user).await.verified);
+            assert!(sent_texts(107).iter().any(|text| text.contains("ЕМИАС узнал пациента")));
+            let rows = Availability::find()
+                .filter(availability::Column::InfoId.eq(user.id))
+                .count(DB.get().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(rows, 2);
+
+            // ЕМИАС отклонил данные: опрос на паузе до их изменения.
+            let user = unverified_profile(108, 22).await;
+            verify_and_poll(&bot, &user, 2).await.unwrap();
+            let state = reload(&user).await;
+            assert_eq!((state.verified, state.polling_paused), (false, true));
+            assert!(sent_texts(108).iter().any(|text| text.contains("ЕМИАС не узнал пациента")));
+        });
+    }
+
+    #[test]
+    fn only_patient_errors_pause_polling() {
+        block_on(async {
+            let (bot, config) = (bot(), config());
+            let user = insert_profile(102).await;
+
+            for _ in 0..3 {
+                record_poll(&bot, &user, &config, Err(service_error())).await;
+            }
+            let state = reload(&user).await;
+            assert_eq!((state.failure_count, state.profile_failure_count, state.polling_paused), (3, 0, false));
+
+            record_poll(&bot, &user, &config, Err(patient_error())).await;
+            record_poll(&bot, &user, &config, Err(service_error())).await;
+            let state = reload(&user).await;
+            assert_eq!((state.profile_failure_count, state.polling_paused), (1, false));
+
+            record_poll(&bot, &user, &config, Err(patient_error())).await;
+            let state = reload(&user).await;
+            assert_eq!((state.profile_failure_count, state.polling_paused), (2, true));
+
+            record_poll(&bot, &user, &config, Ok(())).await;
+            let state = reload(&user).await;
+            assert_eq!((state.failure_count, state.profile_failure_count), (0, 0));
+        });
+    }
+
+    #[test]
+    fn profile_update_during_poll_is_not_overwritten() {
+        block_on(async {
+            let user = insert_profile(103).await;
+
+            // Пока шла проверка, пользователь исправил полис командой `/omscard`.
+            let mut changed: info::ActiveModel = user.clone().into();
+            changed.oms_card = ActiveValue::Set(Some(2323421234567891));
+            changed.failure_count = ActiveValue::Set(4);
+            reset_failures(&mut changed);
+            changed.update(DB.get().unwrap()).await.unwrap();
+
+            let config = PollerConfig { max_failures: 1, ..config() };
+            record_poll(&bot(), &user, &config, Err(patient_error())).await;
+
+            let state = reload(&user).await;
+            assert_eq!((state.failure_count, state.profile_failure_count, state.polling_paused), (0, 0, false));
+        });
+    }
+}
@@ -0,0 +1,136 @@
+use sea_orm::{prelude::*, ActiveValue, QueryOrder};
+use teloxide::types::InlineKeyboardMarkup;
+
+use crate::em_commands::{callback_data::{button, CallbackAction}, pagination::PaginatedKeyboard};
+use crate::entities::{info, prelude::*};
+use crate::{oms, DB};
+
+/// Имя профиля, если пользователь его не задал (профиль, созданный через `/start`).
+pub const DEFAULT_PROFILE_NAME: &str = "Основной";
+
+pub fn display_name(profile: &info::Model) -> String {
+    profile.name.clone().unwrap_or(DEFAULT_PROFILE_NAME.to_string())
+}
+
+/// Подписывает уведомление именем профиля, чтобы было понятно, о ком оно.
+pub fn label(profile: &info::Model, text: impl AsRef<str>) -> String {
+    format!("[{}] {}", display_name(profile), text.as_ref())
+}
+
+pub async fn chat_profiles(chat_id: i64) -> Result<Vec<info::Model>, DbErr> {
+    Info::find()
+        .filter(info::Column::ChatId.eq(chat_id))
+        .order_by_asc(info::Column::Id)
+        .all(DB.get().unwrap())
+        .await
+}
+
+/// Активный профиль чата. Если активный почему-то не отмечен, берётся самый старый.
+pub async fn active_profile(chat_id: i64) -> Result<Option<info::Model>, DbErr> {
+    let profiles = chat_profiles(chat_id).await?;
+    let active = profiles.iter().position(|profile| profile.is_active).unwrap_or(0);
+
+    Ok(profiles.into_iter().nth(active))
+}
+
+/// Профиль чата по id; чужие профили не находятся.
+pub async fn chat_profile(chat_id: i64, profile_id: i32) -> Result<Option<info::Model>, DbErr> {
+    Info::find()
+        .filter(info::Column::ChatId.eq(chat_id))
+        .filter(info::Column::Id.eq(profile_id))
+        .one(DB.get().unwrap())
+        .await
+}
+
+pub async fn add_profile(chat_id: i64, name: &str) -> Result<info::Model, DbErr> {
+    deactivate_all(chat_id).await?;
+
+    info::ActiveModel {
+        chat_id: ActiveValue::Set(chat_id),
+        name: ActiveValue::Set(Some(name.to_string())),
+        is_active: ActiveValue::Set(true),
+        ..Default::default()
+    }.insert(DB.get().unwrap()).await
+}
+
+pub async fn switch_profile(chat_id: i64, profile_id: i32) -> Result<Option<info::Model>, DbErr> {
+    let Some(profile) = chat_profile(chat_id, profile_id).await? else {
+        return Ok(None);
+    };
+
+    deactivate_all(chat_id).await?;
+
+    let mut active: info::ActiveModel = profile.into();
+    active.is_active = ActiveValue::Set(true);
+    active.update(DB.get().unwrap()).await.map(Some)
+}
+
+/// Удаляет профиль вместе с его правилами, снимками и напоминаниями. Если удалён
+/// активный профиль, активным становится самый старый из оставшихся.
+pub async fn remove_profile(chat_id: i64, profile_id: i32) -> Result<bool, DbErr> {
+    let Some(profile) = chat_profile(chat_id, profile_id).await? else {
+        return Ok(false);
+    };
+
+    let was_active = profile.is_active;
+    profile.delete(DB.get().unwrap()).await?;
+
+    if was_active {
+        if let Some(next) = chat_profiles(chat_id).await?.into_iter().next() {
+            let mut next: info::ActiveModel = next.into();
+            next.is_active = ActiveValue::Set(true);
+            next.update(DB.get().unwrap()).await?;
+        }
+    }
+
+    Ok(true)
+}
+
+async fn deactivate_all(chat_id: i64) -> Result<(), DbErr> {
+    Info::update_many()
+        .col_expr(info::Column::IsActive, Expr::value(false))
+        .filter(info::Column::ChatId.eq(chat_id))
+        .exec(DB.get().unwrap())
+        .await?;
+
+    Ok(())
+}
+
+pub fn collect_profiles_data(profiles: &[info::Model]) -> String {
+    if profiles.is_empty() {
+        return "У вас нет профилей. Используйте `/start` или `/addprofile Имя`.\n".to_string();
+    }
+
+    let mut profiles_string = "Ваши профили: \n".to_string();
+    for profile in profiles {
+        profiles_string.push_str(&format!(
+            "{} {}: полис {}, дата рождения {}{}\n",
+            if profile.is_active { "▶" } else { "-" },
+            display_name(profile),
+            profile.oms_card.map_or("не указан".to_string(), oms::format),
+            profile.date_birth.map_or("не указана".to_string(), |d| d.format("%d.%m.%Y").to_string()),
+            if profile.verified { "" } else { " (не подтверждён в ЕМИАС)" }
+        ));
+    }
+    profiles_string.push_str("\nКоманды `/omscard`, `/datebirth`, `/watch` и кнопки записи работают с активным профилем (▶).");
+
+    profiles_string
+}
+
+pub fn profiles_markup(profiles: &[info::Model], page: usize) -> InlineKeyboardMarkup {
+    PaginatedKeyboard::new(profiles, |profile| {
+            let mut row = vec![];
+            if !profile.is_active {
+                row.push(button(
+                    format!("Выбрать «{}»", display_name(profile)),
+                    CallbackAction::ProfileUse { profile_id: profile.id }
+                ));
+            }
+            row.push(button(
+                format!("Удалить «{}»", display_name(profile)),
+                CallbackAction::ProfileDelete { profile_id: profile.id }
+            ));
+            row
+        })
+        .build(page, |page| CallbackAction::Profiles { page })
+}
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+
+use sea_orm::{prelude::*, ActiveValue};
+use teloxide::{prelude::*, types::InlineKeyboardMarkup};
+
+use crate::em_commands::callback_data::{profile_button, CallbackAction};
+use crate::entities::{info, prelude::*, referral_reminder};
+use crate::helper::{plural_form, PluralForm, ReferralAvailability};
+use crate::parsable::appointments::AppointmentInfo;
+use crate::profiles::label;
+use crate::DB;
+
+/// За сколько дней до окончания направления напоминать, если по нему нет записи.
+pub const REMINDER_DAYS: [i64; 2] = [7, 1];
+
+/// Напоминает об истекающих направлениях без записи. Каждое напоминание (направление и
+/// порог из [`REMINDER_DAYS`]) отправляется один раз — отметки хранятся в `referral_reminder`.
+pub async fn run(bot: &Bot, user: &info::Model, referrals: &[ReferralAvailability], appointments: &[AppointmentInfo]) -> Result<(), DbErr> {
+    let db = DB.get().unwrap();
+    let today = chrono::Local::now().date_naive();
+
+    let sent = ReferralReminder::find()
+        .filter(referral_reminder::Column::InfoId.eq(user.id))
+        .all(db)
+        .await?;
+
+    // Напоминания по направлениям, которых больше нет в ЕМИАС, больше не нужны.
+    let current = referrals.iter().map(|r| r.referral_id as i64).collect::<HashSet<i64>>();
+    for row in sent.iter().filter(|row| !current.contains(&row.referral_id)) {
+        row.clone().delete(db).await?;
+    }
+
+    let due = referrals.iter()
+        .filter_map(|referral| {
+            let days_left = (referral.end_date? - today).num_days();
+            let threshold = REMINDER_DAYS.into_iter().filter(|days| days_left >= 0 && days_left <= *days).min()?;
+            let already_sent = sent.iter().any(|row| row.referral_id == referral.referral_id as i64 && row.days_before as i64 == threshold);
+            (!already_sent).then_some((referral, threshold, days_left))
+        })
+        .collect::<Vec<(&ReferralAvailability, i64, i64)>>();
+
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    for (referral, threshold, days_left) in due {
+        if !appointments.iter().any(|a| a.referral_id == Some(referral.referral_id)) {
+            let book_key = profile_button(
+                "Записаться",
+                user.id,
+                CallbackAction::Doctors { referral_id: referral.referral_id, page: 0 }
+            );
+            let markup = InlineKeyboardMarkup::new([[book_key]]);
+
+            let sent = bot.send_message(
+                ChatId(user.chat_id),
+                label(user, format!(
+                    "Направление «{}» действует до {}, {}, а записи по нему нет.",
+                    referral.name,
+                    referral.end_date.unwrap().format("%d.%m.%Y"),
+                    days_left_text(days_left)
+                ))
+            ).reply_markup(markup).await;
+
+            if let Err(err) = sent {
+                log::warn!("Failed to send referral reminder to chat {}: {}", user.chat_id, err);
+                continue;
+            }
+        }
+
+        // Отметка ставится и тогда, когда запись уже есть: о ней напоминать не нужно.
+        ReferralReminder::insert(referral_reminder::ActiveModel {
+            info_id: ActiveValue::Set(user.id),
+            referral_id: ActiveValue::Set(referral.referral_id as i64),
+            days_before: ActiveValue::Set(threshold as i32),
+            sent_at: ActiveValue::Set(now),
+            ..Default::default()
+        }).exec(db).await?;
+    }
+
+    Ok(())
+}
+
+fn days_left_text(days: i64) -> String {
+    match days {
+        0 => "то есть истекает сегодня".to_string(),
+        1 => "то есть истекает завтра".to_string(),
+        n => {
+            let word = match plural_form(n as usize) {
+                PluralForm::One => "день",
+                PluralForm::Few => "дня",
+                PluralForm::Many => "дней"
+            };
+            format!("осталось {} {}", n, word)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{block_on, bot, insert_profile, sent_texts};
+
+    fn referral(referral_id: u64, days_left: i64) -> ReferralAvailability {
+        ReferralAvailability {
+            referral_id,
+            name: "Кардиолог".to_string(),
+            end_date: Some(chrono::Local::now().date_naive() + chrono::Duration::days(days_left)),
+            rooms: vec![]
+        }
+    }
+
+    async fn marks(user: &info::Model) -> Vec<(i64, i32)> {
+        ReferralReminder::find()
+            .filter(referral_reminder::Column::InfoId.eq(user.id))
+            .all(DB.get().unwrap())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| (row.referral_id, row.days_before))
+            .collect()
+    }
+
+    #[test]
+    fn each_threshold_is_reminded_once() {
+        block_on(async {
+            let (bot, user) = (bot(), insert_profile(111).await);
+
+            run(&bot, &user, &[referral(1, 10)], &[]).await.unwrap();
+            assert!(sent_texts(111).is_empty());
+
+            run(&bot, &user, &[referral(1, 5)], &[]).await.unwrap();
+            run(&bot, &user, &[referral(1, 4)], &[]).await.unwrap();
+            assert_eq!(sent_texts(111).len(), 1);
+            assert!(sent_texts(111)[0].contains("осталось 5 дней"));
+
+            run(&bot, &user, &[referral(1, 1)], &[]).await.unwrap();
+            run(&bot, &user, &[referral(1, 0)], &[]).await.unwrap();
+            assert_eq!(sent_texts(111).len(), 2);
+            assert!(sent_texts(111)[1].contains("истекает завтра"));
+            assert_eq!(marks(&user).await, [(1, 7), (1, 1)]);
+
+            // Направление закрыто в ЕМИАС — отметки о нём больше не нужны.
+            run(&bot, &user, &[], &[]).await.unwrap();
+            assert!(marks(&user).await.is_empty());
+        });
+    }
+
+    #[test]
+    fn referral_with_appointment_is_not_reminded() {
+        block_on(async {
+            let (bot, user) = (bot(), insert_profile(112).await);
+            let appointment = serde_json::from_value::<AppointmentInfo>(serde_json::json!({
+                "id": 4100001,
+                "startTime": "2024-09-20T08:00:00+03:00",
+                "endTime": "2024-09-20T08:12:00+03:00",
+                "availableResourceId": 19605506587u64,
+                "complexResourceId": 200992738,
+                "referralId": 1
+            })).unwrap();
+
+            run(&bot, &user, &[referral(1, 5)], &[appointment]).await.unwrap();
+            assert!(sent_texts(112).is_empty());
+            assert_eq!(marks(&user).await, [(1, 7)]);
+        });
+    }
+}
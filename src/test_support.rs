@@ -0,0 +1,175 @@
+//! Окружение unit-тестов бота: база SQLite в памяти со схемой из сущностей, мок ЕМИАС
+//! со сценарием `tests/scenarios/bot.json` и мок Bot API Telegram, который на всё отвечает
+//! успехом и запоминает запросы.
+//!
+//! `DB` и `EMIAS` — глобальные, а пул соединений привязан к рантайму, в котором создан,
+//! поэтому все тесты с базой выполняются через общий рантайм [`block_on`].
+
+use std::{future::Future, sync::Mutex};
+
+use em_bot::mock::{MockEmias, Scenario};
+use sea_orm::{prelude::*, ActiveValue, ConnectOptions, ConnectionTrait, Database, Schema};
+use serde_json::{json, Value};
+use teloxide::Bot;
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream}};
+
+use crate::emias::{EmiasClient, EmiasConfig};
+use crate::entities::{info, prelude::*};
+use crate::{DB, EMIAS};
+
+/// Полис из сценария мок-сервера.
+pub const OMS: i64 = 7788899730000765;
+
+lazy_static::lazy_static! {
+    static ref RUNTIME: tokio::runtime::Runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+}
+
+static MOCK: tokio::sync::OnceCell<MockEmias> = tokio::sync::OnceCell::const_new();
+static TELEGRAM: tokio::sync::OnceCell<String> = tokio::sync::OnceCell::const_new();
+
+/// Запросы к мок Bot API: метод и JSON-тело.
+static TELEGRAM_REQUESTS: Mutex<Vec<(String, Value)>> = Mutex::new(vec![]);
+
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    RUNTIME.block_on(async {
+        init().await;
+        future.await
+    })
+}
+
+async fn init() {
+    let server = MOCK.get_or_init(|| async {
+        let path = format!("{}/tests/scenarios/bot.json", env!("CARGO_MANIFEST_DIR"));
+        MockEmias::start(Scenario::from_file(path).unwrap()).await.unwrap()
+    }).await;
+
+    let _ = EMIAS.set(EmiasClient::new(EmiasConfig {
+        base_url: server.url(),
+        requests_per_second: 0.0,
+        ..EmiasConfig::default()
+    }).unwrap());
+
+    TELEGRAM.get_or_init(|| async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(answer_telegram(stream));
+            }
+        });
+        url
+    }).await;
+
+    DB.get_or_init(|| async {
+        // У каждого соединения с `sqlite::memory:` своя база, поэтому соединение одно.
+        let mut options = ConnectOptions::new("sqlite::memory:");
+        options.max_connections(1).sqlx_logging(false);
+        let db = Database::connect(options).await.unwrap();
+
+        let schema = Schema::new(db.get_database_backend());
+        let tables = [
+            schema.create_table_from_entity(Info),
+            schema.create_table_from_entity(Availability),
+            schema.create_table_from_entity(WatchRule),
+            schema.create_table_from_entity(ReferralReminder),
+            schema.create_table_from_entity(AppointmentReminder),
+            schema.create_table_from_entity(CallbackSession),
+        ];
+        for table in tables {
+            db.execute(db.get_database_backend().build(&table)).await.unwrap();
+        }
+
+        db
+    }).await;
+}
+
+/// Бот, подключённый к мок Bot API.
+pub fn bot() -> Bot {
+    Bot::new("0:test").set_api_url(reqwest::Url::parse(TELEGRAM.get().unwrap()).unwrap())
+}
+
+/// Сколько раз мок ЕМИАС получил вызов метода.
+pub fn emias_calls(method: &str) -> u32 {
+    MOCK.get().unwrap().calls(method)
+}
+
+/// Тексты сообщений, отправленных и отредактированных в чате.
+pub fn sent_texts(chat_id: i64) -> Vec<String> {
+    TELEGRAM_REQUESTS.lock().unwrap()
+        .iter()
+        .filter(|(_, body)| body["chat_id"] == json!(chat_id))
+        .filter_map(|(_, body)| body["text"].as_str().map(str::to_string))
+        .collect()
+}
+
+async fn answer_telegram(mut stream: TcpStream) {
+    let mut request = vec![];
+    let mut chunk = [0u8; 4096];
+    let (head_len, body_len) = loop {
+        let Ok(read @ 1..) = stream.read(&mut chunk).await else {
+            return;
+        };
+        request.extend_from_slice(&chunk[..read]);
+
+        if let Some(pos) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&request[..pos]).to_lowercase();
+            let body_len = head.lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|len| len.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            break (pos + 4, body_len);
+        }
+    };
+    while request.len() < head_len + body_len {
+        let Ok(read @ 1..) = stream.read(&mut chunk).await else {
+            break;
+        };
+        request.extend_from_slice(&chunk[..read]);
+    }
+
+    let head = String::from_utf8_lossy(&request[..head_len]).to_string();
+    let method = head.split_whitespace().nth(1).and_then(|path| path.rsplit('/').next()).unwrap_or_default().to_string();
+    let body = serde_json::from_slice::<Value>(&request[head_len..]).unwrap_or(Value::Null);
+
+    let result = match method.as_str() {
+        "answerCallbackQuery" => json!(true),
+        _ => json!({
+            "message_id": body["message_id"].as_i64().unwrap_or(1),
+            "date": 0,
+            "chat": { "id": body["chat_id"], "type": "private", "first_name": "Test" },
+            "text": body["text"].as_str().unwrap_or_default()
+        })
+    };
+    TELEGRAM_REQUESTS.lock().unwrap().push((method, body));
+
+    let payload = json!({ "ok": true, "result": result }).to_string();
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        payload.len(), payload
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+}
+
+/// Заполненный и проверенный профиль с полисом из сценария мок-сервера.
+pub async fn insert_profile(chat_id: i64) -> info::Model {
+    info::ActiveModel {
+        chat_id: ActiveValue::Set(chat_id),
+        oms_card: ActiveValue::Set(Some(OMS)),
+        date_birth: ActiveValue::Set(Some(Date::from_ymd_opt(2001, 11, 19).unwrap())),
+        last_polled_at: ActiveValue::Set(None),
+        next_poll_at: ActiveValue::Set(None),
+        failure_count: ActiveValue::Set(0),
+        last_error: ActiveValue::Set(None),
+        last_failed_at: ActiveValue::Set(None),
+        failure_notified: ActiveValue::Set(false),
+        polling_paused: ActiveValue::Set(false),
+        name: ActiveValue::Set(None),
+        is_active: ActiveValue::Set(true),
+        verified: ActiveValue::Set(true),
+        profile_failure_count: ActiveValue::Set(0),
+        ..Default::default()
+    }.insert(DB.get().unwrap()).await.unwrap()
+}
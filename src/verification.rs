@@ -0,0 +1,119 @@
+use sea_orm::{prelude::*, ActiveValue};
+use teloxide::{prelude::*, types::InlineKeyboardMarkup};
+
+use crate::em_commands::callback_data::{button, CallbackAction};
+use crate::entities::info;
+use crate::error::EmiasError;
+use crate::helper::{plural_form, PluralForm};
+use crate::poller::reset_failures;
+use crate::profiles::label;
+use crate::{oms, DB, EMIAS};
+
+/// Итог живой проверки профиля запросом `getReferralsInfo`.
+pub enum Verification {
+    /// ЕМИАС узнал пациента; число — сколько у него направлений.
+    Verified(usize),
+    /// ЕМИАС отклонил полис или дату рождения.
+    Rejected(EmiasError),
+    /// ЕМИАС недоступен, проверить не удалось. Отметка профиля не меняется.
+    Unavailable(EmiasError),
+}
+
+/// Проверяет заполненный профиль в ЕМИАС и сохраняет отметку `verified`. `None` — у профиля
+/// не указан полис или дата рождения.
+///
+/// Отклонённый профиль ставится на паузу до изменения данных. Непроверенный профиль без
+/// паузы (ЕМИАС был недоступен) поллер проверяет повторно, с той же задержкой, что и
+/// после неудачного опроса.
+pub async fn verify(profile: &info::Model) -> Result<Option<Verification>, DbErr> {
+    let (Some(oms), Some(date_birth)) = (profile.oms_card, profile.date_birth) else {
+        return Ok(None);
+    };
+
+    let verification = match EMIAS.get().unwrap().get_referrals_info(&oms::format(oms), &date_birth).await {
+        Ok(referrals) => Verification::Verified(referrals.result.len()),
+        Err(err) if err.is_profile_error() => Verification::Rejected(err),
+        Err(err) => Verification::Unavailable(err)
+    };
+
+    let mut active: info::ActiveModel = profile.clone().into();
+    match &verification {
+        Verification::Verified(_) => {
+            active.verified = ActiveValue::Set(true);
+            active.next_poll_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
+            reset_failures(&mut active);
+        },
+        Verification::Rejected(_) => {
+            active.verified = ActiveValue::Set(false);
+            active.polling_paused = ActiveValue::Set(true);
+        },
+        Verification::Unavailable(_) => return Ok(Some(verification))
+    }
+    active.update(DB.get().unwrap()).await?;
+
+    Ok(Some(verification))
+}
+
+/// Проверяет профиль и сразу сообщает пользователю, узнал ли его ЕМИАС. Если ЕМИАС
+/// недоступен, к сообщению прикрепляется кнопка повторной проверки.
+pub async fn verify_and_report(bot: &Bot, profile: &info::Model) {
+    match verify(profile).await {
+        Ok(Some(verification)) => report(bot, profile, &verification).await,
+        Ok(None) => {},
+        Err(err) => {
+            log::error!("Failed to store verification of profile {}: {}", profile.id, err);
+            let sent = bot.send_message(
+                ChatId(profile.chat_id),
+                "Не удалось сохранить результат проверки. Попробуйте позже или обратитесь к автору этого безобразия."
+            ).await;
+            if let Err(err) = sent {
+                log::error!("Failed to send verification result to chat {}: {}", profile.chat_id, err);
+            }
+        }
+    }
+}
+
+/// Сообщает пользователю итог проверки профиля.
+pub async fn report(bot: &Bot, profile: &info::Model, verification: &Verification) {
+    let chat_id = ChatId(profile.chat_id);
+
+    let sent = match verification {
+        Verification::Verified(n) => {
+            let word = match plural_form(*n) {
+                PluralForm::One => "направление",
+                PluralForm::Few => "направления",
+                PluralForm::Many => "направлений"
+            };
+            bot.send_message(
+                chat_id,
+                label(profile, format!("ЕМИАС узнал пациента: найдено {} {}. Бот будет следить за изменениями.", n, word))
+            ).await
+        },
+        Verification::Rejected(err) => {
+            bot.send_message(
+                chat_id,
+                label(profile, format!(
+                    "ЕМИАС не узнал пациента по этим данным. \n{} \nПока данные не исправлены, бот не будет проверять направления этого профиля.",
+                    err.user_message()
+                ))
+            ).await
+        },
+        Verification::Unavailable(err) => {
+            let retry_key = button(
+                "Проверить ещё раз",
+                CallbackAction::ProfileVerify { profile_id: profile.id }
+            );
+            bot.send_message(
+                chat_id,
+                label(profile, format!(
+                    "Не удалось проверить данные в ЕМИАС. \n{} \nБот сам повторит проверку позже и начнёт следить за направлениями, когда она пройдёт.",
+                    err.user_message()
+                ))
+            ).reply_markup(InlineKeyboardMarkup::new([[retry_key]])).await
+        }
+    };
+
+    if let Err(err) = sent {
+        log::error!("Failed to send verification result to chat {}: {}", profile.chat_id, err);
+    }
+}
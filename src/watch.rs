@@ -0,0 +1,253 @@
+use chrono::NaiveDate;
+
+use crate::entities::watch_rule;
+use crate::helper::FreeRoom;
+
+/// Условия правила отслеживания до сохранения в базу.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WatchRuleDraft {
+    pub referral_id: Option<u64>,
+    pub doctor_id: Option<u64>,
+    pub doctor_name: Option<String>,
+    pub lpu_id: Option<u64>,
+    pub date_from: Option<NaiveDate>,
+    pub date_to: Option<NaiveDate>,
+    pub auto_book: bool,
+}
+
+pub const WATCH_HELP: &str = "Формат: `/watch условие=значение ...`. Условия: \n\
+- `направление=<id>` — только это направление; \n\
+- `врач=<фамилия или id>` — только этот врач или кабинет; \n\
+- `клиника=<id>` — только эта поликлиника; \n\
+- `с=ДД.ММ.ГГГГ`, `до=ДД.ММ.ГГГГ` — только даты в этом диапазоне; \n\
+- `автозапись=да` — сразу записать на первое подходящее время (нужно указать направление). \n\
+Пример: `/watch врач=Иванов до=01.12.2024`.";
+
+/// Разбирает аргументы команды `/watch`. Значение условия продолжается до следующего
+/// `ключ=`, поэтому фамилию с инициалами можно писать через пробел.
+pub fn parse_watch_rule(args: &str) -> Result<WatchRuleDraft, String> {
+    let mut pairs: Vec<(String, String)> = vec![];
+
+    for token in args.split_whitespace() {
+        match token.split_once('=') {
+            Some((key, value)) => pairs.push((key.to_lowercase(), value.to_string())),
+            None => match pairs.last_mut() {
+                Some((_, value)) => {
+                    value.push(' ');
+                    value.push_str(token);
+                },
+                None => return Err(format!("Не понимаю «{}»: условие должно иметь вид `ключ=значение`.", token))
+            }
+        }
+    }
+
+    if pairs.is_empty() {
+        return Err("Не указано ни одного условия.".to_string());
+    }
+
+    let mut draft = WatchRuleDraft::default();
+    for (key, value) in pairs {
+        match key.as_str() {
+            "направление" | "ref" => {
+                draft.referral_id = Some(value.parse().map_err(|_| format!("Номер направления должен быть числом, а не «{}».", value))?);
+            },
+            "врач" | "doctor" => match value.parse() {
+                Ok(id) => draft.doctor_id = Some(id),
+                Err(_) => draft.doctor_name = Some(value)
+            },
+            "клиника" | "lpu" => {
+                draft.lpu_id = Some(value.parse().map_err(|_| format!("Номер поликлиники должен быть числом, а не «{}».", value))?);
+            },
+            "с" | "from" => draft.date_from = Some(parse_date(&value)?),
+            "до" | "to" => draft.date_to = Some(parse_date(&value)?),
+            "автозапись" | "auto" => draft.auto_book = match value.to_lowercase().as_str() {
+                "да" | "yes" | "on" => true,
+                "нет" | "no" | "off" => false,
+                _ => return Err(format!("Автозапись включается значением «да» или «нет», а не «{}».", value))
+            },
+            _ => return Err(format!("Неизвестное условие «{}».", key))
+        }
+    }
+
+    if let (Some(from), Some(to)) = (draft.date_from, draft.date_to) {
+        if from > to {
+            return Err("Дата «с» позже даты «до».".to_string());
+        }
+    }
+
+    if draft.auto_book && draft.referral_id.is_none() {
+        return Err("Для автозаписи укажите направление: `направление=<id>`.".to_string());
+    }
+
+    Ok(draft)
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%d.%m.%Y").map_err(|_| format!("Дата «{}» должна быть в формате ДД.ММ.ГГГГ.", value))
+}
+
+impl watch_rule::Model {
+    /// Может ли у кабинета быть время, подходящее под правило. Про кабинет известна только
+    /// ближайшая свободная дата, поэтому по ней отсекаются лишь кабинеты, где свободно только
+    /// после `до`; ближайшая дата раньше `с` кабинет не исключает — дни расписания
+    /// проверяются отдельно, см. [`Self::matches_date`].
+    pub fn matches(&self, referral_id: u64, room: &FreeRoom) -> bool {
+        self.referral_id.is_none_or(|id| id as u64 == referral_id)
+            && self.doctor_id.is_none_or(|id| id as u64 == room.resource_id)
+            && self.doctor_name.as_ref().is_none_or(|name| room.resource_name.to_lowercase().contains(&name.to_lowercase()))
+            && self.lpu_id.is_none_or(|id| id == room.lpu_id as i64)
+            && self.date_to.is_none_or(|to| room.date <= to)
+    }
+
+    /// Подходит ли под правило конкретный день расписания (а не только ближайшая дата кабинета).
+    pub fn matches_date(&self, date: NaiveDate) -> bool {
+        self.date_from.is_none_or(|from| date >= from) && self.date_to.is_none_or(|to| date <= to)
+    }
+
+    pub fn description(&self) -> String {
+        let mut conditions = vec![];
+
+        if let Some(id) = self.referral_id {
+            conditions.push(format!("направление {}", id));
+        }
+        if let Some(id) = self.doctor_id {
+            conditions.push(format!("врач/кабинет {}", id));
+        }
+        if let Some(name) = &self.doctor_name {
+            conditions.push(format!("врач «{}»", name));
+        }
+        if let Some(id) = self.lpu_id {
+            conditions.push(format!("поликлиника {}", id));
+        }
+        if let Some(from) = self.date_from {
+            conditions.push(format!("с {}", from.format("%d.%m.%Y")));
+        }
+        if let Some(to) = self.date_to {
+            conditions.push(format!("до {}", to.format("%d.%m.%Y")));
+        }
+
+        let description = if conditions.is_empty() {
+            "любые слоты".to_string()
+        } else {
+            conditions.join(", ")
+        };
+
+        if self.auto_book {
+            format!("{} (автозапись)", description)
+        } else {
+            description
+        }
+    }
+}
+
+pub fn collect_watch_rules_data(rules: &[watch_rule::Model]) -> String {
+    if rules.is_empty() {
+        return "Правил отслеживания нет: бот сообщает об изменениях по всем направлениям.\n".to_string();
+    }
+
+    let mut rules_string = "Ваши правила отслеживания: \n".to_string();
+    for rule in rules {
+        rules_string.push_str(&format!("#{}: {}\n", rule.id, rule.description()));
+    }
+
+    rules_string
+}
+
+/// Правила пользователя как фильтр кабинетов для уведомлений: без правил пользователь
+/// следит за всеми направлениями, иначе — только за кабинетами, подходящими хотя бы под одно
+/// правило. В уведомлении сообщается ближайшая дата кабинета, поэтому она сама должна
+/// попадать в окно `с`–`до`: иначе правило «с 01.12» присылало бы даты из ноября.
+pub fn rules_filter(rules: &[watch_rule::Model]) -> impl Fn(u64, &FreeRoom) -> bool + '_ {
+    move |referral_id, room| {
+        rules.is_empty() || rules.iter().any(|rule| rule.matches(referral_id, room) && rule.matches_date(room.date))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32, month: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn rule(draft: WatchRuleDraft) -> watch_rule::Model {
+        watch_rule::Model {
+            id: 1,
+            info_id: 1,
+            referral_id: draft.referral_id.map(|id| id as i64),
+            doctor_id: draft.doctor_id.map(|id| id as i64),
+            doctor_name: draft.doctor_name,
+            lpu_id: draft.lpu_id.map(|id| id as i64),
+            date_from: draft.date_from,
+            date_to: draft.date_to,
+            created_at: Default::default(),
+            auto_book: draft.auto_book,
+        }
+    }
+
+    fn room(date: NaiveDate) -> FreeRoom {
+        FreeRoom {
+            resource_id: 19605506587,
+            complex_resource_id: 200992738,
+            resource_name: "Иванов Иван Иванович".to_string(),
+            lpu_id: 10000418,
+            lpu_short_name: "ГП № 2".to_string(),
+            date
+        }
+    }
+
+    #[test]
+    fn parses_conditions() {
+        let draft = parse_watch_rule("направление=42 врач=Иванов И. И. клиника=7 с=01.12.2024 до=20.12.2024 автозапись=да").unwrap();
+
+        assert_eq!(draft, WatchRuleDraft {
+            referral_id: Some(42),
+            doctor_id: None,
+            doctor_name: Some("Иванов И. И.".to_string()),
+            lpu_id: Some(7),
+            date_from: Some(date(1, 12)),
+            date_to: Some(date(20, 12)),
+            auto_book: true,
+        });
+        assert_eq!(parse_watch_rule("doctor=19605506587").unwrap().doctor_id, Some(19605506587));
+    }
+
+    #[test]
+    fn rejects_bad_conditions() {
+        assert!(parse_watch_rule("").is_err());
+        assert!(parse_watch_rule("Иванов").is_err());
+        assert!(parse_watch_rule("цвет=синий").is_err());
+        assert!(parse_watch_rule("направление=abc").is_err());
+        assert!(parse_watch_rule("с=2024-12-01").is_err());
+        assert!(parse_watch_rule("с=20.12.2024 до=01.12.2024").is_err());
+        assert!(parse_watch_rule("автозапись=да").is_err());
+        assert!(parse_watch_rule("направление=1 автозапись=может").is_err());
+    }
+
+    #[test]
+    fn matches_room_conditions() {
+        let rule = rule(parse_watch_rule("направление=1 врач=иванов клиника=10000418").unwrap());
+
+        assert!(rule.matches(1, &room(date(1, 12))));
+        assert!(!rule.matches(2, &room(date(1, 12))));
+        assert!(!rule.matches(1, &FreeRoom { resource_name: "Петров П. П.".to_string(), ..room(date(1, 12)) }));
+        assert!(!rule.matches(1, &FreeRoom { lpu_id: 1, ..room(date(1, 12)) }));
+    }
+
+    #[test]
+    fn date_window_keeps_rooms_with_earlier_nearest_date() {
+        let rule = rule(parse_watch_rule("с=01.12.2024 до=20.12.2024").unwrap());
+
+        // Ближайшая дата раньше окна: в самом окне у кабинета тоже может быть время.
+        assert!(rule.matches(1, &room(date(25, 11))));
+        assert!(rule.matches(1, &room(date(10, 12))));
+        // Ближайшая дата позже окна: раньше неё свободного времени нет.
+        assert!(!rule.matches(1, &room(date(21, 12))));
+
+        assert!(!rule.matches_date(date(25, 11)));
+        assert!(rule.matches_date(date(1, 12)));
+        assert!(rule.matches_date(date(20, 12)));
+        assert!(!rule.matches_date(date(21, 12)));
+    }
+}
@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use em_bot::{
+    emias::{EmiasClient, EmiasConfig},
+    error::{EmiasError, RpcErrorKind},
+    limiter::RetryPolicy,
+    mock::{MockEmias, Scenario},
+    parsable::doctors::ResultType,
+};
+
+const OMS: &str = "7788899730000765";
+
+fn birth_date() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2001, 11, 19).unwrap()
+}
+
+async fn start(scenario: &str) -> (MockEmias, EmiasClient) {
+    let path = format!("{}/tests/scenarios/{}.json", env!("CARGO_MANIFEST_DIR"), scenario);
+    let server = MockEmias::start(Scenario::from_file(path).unwrap()).await.unwrap();
+
+    let client = EmiasClient::new(EmiasConfig {
+        base_url: server.url(),
+        requests_per_second: 0.0,
+        retry: RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        },
+        ..EmiasConfig::default()
+    }).unwrap();
+
+    (server, client)
+}
+
+#[tokio::test]
+async fn referrals_are_parsed_and_cached() {
+    let (server, client) = start("basic").await;
+
+    let referrals = client.get_referrals_info(OMS, &birth_date()).await.unwrap();
+    assert_eq!(referrals.result.len(), 2);
+    assert_eq!(referrals.result[0].to_doctor.as_ref().unwrap().speciality_name, "Кардиолог");
+    assert_eq!(referrals.result[1].to_ldp.as_ref().unwrap().ldp_type_name, "УЗИ брюшной полости");
+
+    client.get_referrals_info(OMS, &birth_date()).await.unwrap();
+    assert_eq!(server.calls("getReferralsInfo"), 1);
+
+    client.invalidate(OMS, &birth_date());
+    client.get_referrals_info(OMS, &birth_date()).await.unwrap();
+    assert_eq!(server.calls("getReferralsInfo"), 2);
+}
+
+#[tokio::test]
+async fn booking_invalidates_cache() {
+    let (server, client) = start("basic").await;
+    let slot_start = chrono::DateTime::parse_from_rfc3339("2024-09-20T08:00:00+03:00").unwrap();
+    let slot_end = chrono::DateTime::parse_from_rfc3339("2024-09-20T08:12:00+03:00").unwrap();
+
+    client.get_referrals_info(OMS, &birth_date()).await.unwrap();
+    client.get_doctors_info(OMS, &birth_date(), 172704541983).await.unwrap();
+
+    let created = client.create_appointment(OMS, &birth_date(), 19605506587, 200992738, 172704541983, &slot_start, &slot_end).await.unwrap();
+    assert_eq!(created.result.appointment_id, 4100001);
+
+    client.get_referrals_info(OMS, &birth_date()).await.unwrap();
+    client.get_doctors_info(OMS, &birth_date(), 172704541983).await.unwrap();
+    assert_eq!(server.calls("getReferralsInfo"), 2);
+    assert_eq!(server.calls("getDoctorsInfo"), 2);
+}
+
+#[tokio::test]
+async fn doctors_and_ldp_arrays_are_distinguished() {
+    let (_server, client) = start("basic").await;
+
+    let doctors = client.get_doctors_info(OMS, &birth_date(), 172704541983).await.unwrap();
+    assert!(matches!(doctors.result, ResultType::DocArray(ref d) if d[0].complex_resource[0].room.is_some()));
+
+    let ldps = client.get_doctors_info(OMS, &birth_date(), 172704541984).await.unwrap();
+    assert!(matches!(ldps.result, ResultType::LdpArray(ref l) if l[0].complex_resource[0].room.is_none()));
+}
+
+#[tokio::test]
+async fn slots_appear_over_time() {
+    let (_server, client) = start("basic").await;
+    let birth_date = birth_date();
+    let schedule = || client.get_schedule_info(OMS, &birth_date, 19605506587, 200992738, Some(172704541983));
+
+    for _ in 0..2 {
+        assert!(schedule().await.unwrap().result.schedule_of_day.is_empty());
+    }
+
+    let schedule = schedule().await.unwrap();
+    assert_eq!(schedule.result.schedule_of_day[0].slots().count(), 2);
+}
+
+#[tokio::test]
+async fn rpc_error_is_typed() {
+    let (_server, client) = start("errors").await;
+
+    let err = client.get_referrals_info(OMS, &birth_date()).await.unwrap_err();
+    assert!(matches!(err, EmiasError::Rpc { code: 1001, ref message, .. } if message == "Пациент не найден"));
+    assert!(err.user_message().contains(err.request_id()));
+    assert_eq!(err.rpc_kind(), Some(RpcErrorKind::Patient));
+    assert!(err.user_message().contains("/omscard"));
+}
+
+#[tokio::test]
+async fn response_to_another_request_is_rejected() {
+    let (_server, client) = start("errors").await;
+
+    let err = client.get_appointments_info(OMS, &birth_date()).await.unwrap_err();
+    assert!(matches!(
+        err,
+        EmiasError::IdMismatch { ref request_id, response_id: Some(ref id) } if id == "00000000-0000-0000-0000-000000000000" && request_id != id
+    ));
+}
+
+#[test]
+fn rpc_errors_are_classified_by_code_and_message() {
+    assert_eq!(RpcErrorKind::classify(1001, ""), RpcErrorKind::Patient);
+    assert_eq!(RpcErrorKind::classify(-32602, "Invalid params"), RpcErrorKind::Patient);
+    assert_eq!(RpcErrorKind::classify(-32603, "Internal error"), RpcErrorKind::Maintenance);
+    assert_eq!(RpcErrorKind::classify(-32050, ""), RpcErrorKind::Maintenance);
+    assert_eq!(RpcErrorKind::classify(500, "Ведутся технические работы"), RpcErrorKind::Maintenance);
+    assert_eq!(RpcErrorKind::classify(409, "Выбранное время уже занято"), RpcErrorKind::SlotUnavailable);
+    assert_eq!(RpcErrorKind::classify(400, "Неверный номер полиса"), RpcErrorKind::Patient);
+    assert_eq!(RpcErrorKind::classify(410, "Срок действия направления истёк"), RpcErrorKind::Other);
+}
+
+#[tokio::test]
+async fn server_errors_are_retried() {
+    let (server, client) = start("errors").await;
+
+    let doctors = client.get_doctors_info(OMS, &birth_date(), 1).await.unwrap();
+    assert!(matches!(doctors.result, ResultType::EmptyObject(_)));
+    assert_eq!(server.calls("getDoctorsInfo"), 2);
+}